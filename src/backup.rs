@@ -0,0 +1,253 @@
+use crate::{
+    journal::{self, RefMove},
+    GitRebrandError,
+};
+use anyhow::{Context, Result};
+use chrono::{Duration, Local, NaiveDateTime};
+use git2::{Buf, ObjectType, Oid, Repository};
+use log::{debug, info, warn};
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const BUNDLE_SIGNATURE: &[u8] = b"# v2 git bundle\n";
+
+/// Namespace holding the original value of every rewritten ref, one directory per run
+pub const BACKUP_NAMESPACE: &str = "refs/rebrand/backup/";
+
+/// Name of the backup of `refname` taken by a run
+pub fn backup_ref_name(run_id: &str, refname: &str) -> String {
+    format!("{}{}/{}", BACKUP_NAMESPACE, run_id, refname)
+}
+
+// Points a backup ref at the original object of every ref about to move
+pub(crate) fn create(repo: &Repository, run_id: &str, refs: &[(String, Oid)]) -> Result<()> {
+    for (name, original) in refs {
+        let backup = backup_ref_name(run_id, name);
+        debug!("Creating backup ref: {}", backup);
+        repo.reference(
+            &backup,
+            *original,
+            false,
+            &format!("rebrand: backup {}", run_id),
+        )
+        .map_err(|e| GitRebrandError::BackupFailed(format!("{}: {}", backup, e)))?;
+    }
+    info!(
+        "Backed up {} references under {}{}/",
+        refs.len(),
+        BACKUP_NAMESPACE,
+        run_id
+    );
+    Ok(())
+}
+
+/// Writes the original value of every ref into a self-contained `git bundle` (v2) file.
+///
+/// When `path` is a directory the bundle is named after the run. Returns the file written.
+pub(crate) fn write_bundle(
+    repo: &Repository,
+    path: &Path,
+    run_id: &str,
+    refs: &[(String, Oid)],
+) -> Result<PathBuf> {
+    let path = if path.is_dir() {
+        path.join(format!("rebrand-{}.bundle", run_id))
+    } else {
+        path.to_path_buf()
+    };
+
+    let mut header = BUNDLE_SIGNATURE.to_vec();
+    let mut builder = repo.packbuilder()?;
+    let mut revwalk = repo.revwalk()?;
+    for (name, original) in refs {
+        header.extend_from_slice(format!("{} {}\n", original, name).as_bytes());
+        // Annotated tags are packed along with every tag object in their chain
+        let mut object = repo.find_object(*original, None)?;
+        while object.kind() == Some(ObjectType::Tag) {
+            builder.insert_object(object.id(), None)?;
+            object = object.peel(ObjectType::Any)?;
+        }
+        revwalk.push(object.peel_to_commit()?.id())?;
+    }
+    header.push(b'\n');
+    builder.insert_walk(&mut revwalk)?;
+
+    let mut pack = Buf::new();
+    builder.write_buf(&mut pack)?;
+    let mut file = fs::File::create(&path)
+        .with_context(|| format!("Failed to create bundle: {}", path.display()))?;
+    file.write_all(&header)?;
+    file.write_all(&pack)?;
+
+    info!(
+        "Wrote bundle of {} references ({} objects) to {}",
+        refs.len(),
+        builder.object_count(),
+        path.display()
+    );
+    Ok(path)
+}
+
+/// Imports the objects of a `git bundle` file and points every ref it lists back at its
+/// bundled value. Returns the restored ref names.
+pub fn restore_bundle<P: AsRef<Path>, B: AsRef<Path>>(
+    repo_path: P,
+    bundle_path: B,
+) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path.as_ref()).context("Failed to open git repository")?;
+    let bundle_path = bundle_path.as_ref();
+    let content = fs::read(bundle_path)
+        .with_context(|| format!("Failed to read bundle: {}", bundle_path.display()))?;
+    let invalid = |reason: &str| {
+        GitRebrandError::InvalidBundle(format!("{}: {}", bundle_path.display(), reason))
+    };
+
+    let mut rest = content
+        .strip_prefix(BUNDLE_SIGNATURE)
+        .ok_or_else(|| invalid("not a v2 git bundle"))?;
+    let mut refs = Vec::new();
+    loop {
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| invalid("truncated header"))?;
+        let line = std::str::from_utf8(&rest[..end]).map_err(|_| invalid("invalid header"))?;
+        rest = &rest[end + 1..];
+        if line.is_empty() {
+            break;
+        }
+        if let Some(prerequisite) = line.strip_prefix('-') {
+            let id = prerequisite.split(' ').next().unwrap_or_default();
+            let present =
+                Oid::from_str(id).is_ok_and(|id| repo.odb().is_ok_and(|odb| odb.exists(id)));
+            if !present {
+                return Err(invalid(&format!("missing prerequisite commit {}", id)).into());
+            }
+            continue;
+        }
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| invalid("invalid ref line"))?;
+        let id = Oid::from_str(id).map_err(|_| invalid("invalid object id"))?;
+        refs.push(RefMove {
+            name: name.to_string(),
+            expected: None,
+            target: id,
+        });
+    }
+
+    let odb = repo.odb()?;
+    let mut writer = odb.packwriter()?;
+    writer.write_all(rest)?;
+    writer.commit()?;
+
+    journal::restore_refs(
+        &repo,
+        &refs,
+        &format!("rebrand: restore {}", bundle_path.display()),
+    )?;
+    info!(
+        "Restored {} references from {}",
+        refs.len(),
+        bundle_path.display()
+    );
+    Ok(refs.into_iter().map(|m| m.name).collect())
+}
+
+pub(crate) fn exists(repo: &Repository, run_id: &str) -> Result<bool> {
+    let glob = format!("{}{}/*", BACKUP_NAMESPACE, run_id);
+    Ok(repo.references_glob(&glob)?.next().is_some())
+}
+
+/// Deletes backups of runs older than `older_than` (`30d`, `12h`, `2w`, ...) and
+/// returns the ids of the pruned runs
+pub fn prune<P: AsRef<Path>>(repo_path: P, older_than: &str) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path.as_ref()).context("Failed to open git repository")?;
+    let cutoff = cutoff(Local::now().naive_local(), older_than)?;
+
+    let mut pruned = BTreeSet::new();
+    let glob = format!("{}*", BACKUP_NAMESPACE);
+    let names: Vec<String> = repo
+        .references_glob(&glob)?
+        .filter_map(|r| r.ok()?.name().map(str::to_string))
+        .collect();
+    for name in names {
+        let Some(run_id) = name[BACKUP_NAMESPACE.len()..].split('/').next() else {
+            continue;
+        };
+        let Some(created) = run_time(run_id) else {
+            warn!("Skipping backup with an unrecognized run id: {}", name);
+            continue;
+        };
+        if created < cutoff {
+            debug!("Deleting backup ref: {}", name);
+            repo.find_reference(&name)?.delete()?;
+            pruned.insert(run_id.to_string());
+        }
+    }
+
+    info!("Pruned backups of {} runs", pruned.len());
+    Ok(pruned.into_iter().collect())
+}
+
+// Run ids start with their local creation time, e.g. `20240321-123456`
+fn run_time(run_id: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(run_id.get(..15)?, "%Y%m%d-%H%M%S").ok()
+}
+
+// The time `age` before `now`; backups created earlier than it are pruned
+fn cutoff(now: NaiveDateTime, age: &str) -> Result<NaiveDateTime, GitRebrandError> {
+    now.checked_sub_signed(parse_age(age)?).ok_or_else(|| {
+        GitRebrandError::InvalidConfig(format!("Age '{}' reaches too far back", age))
+    })
+}
+
+// Parses ages such as `90s`, `45m`, `12h`, `30d` or `2w`
+fn parse_age(age: &str) -> Result<Duration, GitRebrandError> {
+    let invalid = || {
+        GitRebrandError::InvalidConfig(format!(
+            "Invalid age '{}': expected a non-negative number followed by s, m, h, d or w",
+            age
+        ))
+    };
+    let unit = age.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = age[..age.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    // A negative age would put the cutoff in the future and prune every backup
+    if amount < 0 {
+        return Err(invalid());
+    }
+    let duration = match unit {
+        's' => Duration::try_seconds(amount),
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => return Err(invalid()),
+    };
+    duration.ok_or_else(|| GitRebrandError::InvalidConfig(format!("Age '{}' is out of range", age)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_range_and_negative_ages_rejected() {
+        let now = Local::now().naive_local();
+        for age in ["99999999999999d", "-5d", "-1s", "5y", ""] {
+            assert!(
+                matches!(cutoff(now, age), Err(GitRebrandError::InvalidConfig(_))),
+                "{} was accepted",
+                age
+            );
+        }
+        assert_eq!(cutoff(now, "2w").unwrap(), now - Duration::days(14));
+        assert_eq!(cutoff(now, "0s").unwrap(), now);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    backup,
+    worktree::{self, common_dir, has_changes},
+    GitRebrandError,
+};
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use git2::{Oid, Repository};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// A reference moved by a rewrite run, with both ids as hex strings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefChange {
+    pub name: String,
+    pub old: String,
+    pub new: String,
+}
+
+// One rewrite run as recorded in `.git/rebrand/runs/<id>.yml`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: String,
+    /// When the run finished, in RFC 3339 format
+    pub timestamp: String,
+    pub refs: Vec<RefChange>,
+    /// When the run was undone, if it was
+    #[serde(default)]
+    pub undone_at: Option<String>,
+}
+
+// A reference to move; `expected` is the value it must still have once locked
+#[derive(Debug, Clone)]
+pub(crate) struct RefMove {
+    pub(crate) name: String,
+    pub(crate) expected: Option<Oid>,
+    pub(crate) target: Oid,
+}
+
+impl RunRecord {
+    pub(crate) fn new(id: String, refs: Vec<RefChange>) -> Self {
+        Self {
+            id,
+            timestamp: now(),
+            refs,
+            undone_at: None,
+        }
+    }
+}
+
+/// Lists every recorded run of the repository, oldest first
+pub fn history<P: AsRef<Path>>(repo_path: P) -> Result<Vec<RunRecord>> {
+    let repo = open(repo_path.as_ref())?;
+    load_runs(&repo)
+}
+
+/// Moves every reference of a run back to where it was before the rewrite.
+///
+/// Without a run id the most recent run that has not been undone is restored. The
+/// references are updated together, and only if none of them moved since the run.
+pub fn undo<P: AsRef<Path>>(repo_path: P, run_id: Option<&str>) -> Result<RunRecord> {
+    let repo = open(repo_path.as_ref())?;
+    let runs = load_runs(&repo)?;
+    let mut run = match run_id {
+        Some(id) => runs
+            .into_iter()
+            .find(|run| run.id == id)
+            .ok_or_else(|| GitRebrandError::UndoFailed(format!("No rewrite run with id {}", id)))?,
+        None => runs
+            .into_iter()
+            .rev()
+            .find(|run| run.undone_at.is_none())
+            .ok_or_else(|| GitRebrandError::UndoFailed("No rewrite runs to undo".to_string()))?,
+    };
+    if run.undone_at.is_some() {
+        return Err(
+            GitRebrandError::UndoFailed(format!("Run {} was already undone", run.id)).into(),
+        );
+    }
+
+    let mut moves = Vec::new();
+    for change in &run.refs {
+        let old = Oid::from_str(&change.old)?;
+        let new = Oid::from_str(&change.new)?;
+        let current = repo.refname_to_id(&change.name).ok();
+        if current != Some(new) {
+            return Err(GitRebrandError::UndoFailed(format!(
+                "{} has moved since run {}",
+                change.name, run.id
+            ))
+            .into());
+        }
+        moves.push(RefMove {
+            name: change.name.clone(),
+            expected: Some(new),
+            target: old,
+        });
+    }
+
+    restore_refs(&repo, &moves, &format!("rebrand: undo {}", run.id))?;
+
+    run.undone_at = Some(now());
+    save(&repo, &run)?;
+    info!("Restored {} references from run {}", run.refs.len(), run.id);
+    Ok(run)
+}
+
+/// Picks an id for a new run; ids are timestamps, suffixed when several runs share a second
+pub(crate) fn new_run_id(repo: &Repository) -> Result<String> {
+    let base = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let dir = runs_dir(repo);
+    let mut id = base.clone();
+    let mut suffix = 1;
+    while dir.join(format!("{}.yml", id)).exists() || backup::exists(repo, &id)? {
+        suffix += 1;
+        id = format!("{}-{}", base, suffix);
+    }
+    Ok(id)
+}
+
+pub(crate) fn save(repo: &Repository, run: &RunRecord) -> Result<()> {
+    let dir = runs_dir(repo);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create journal directory: {}", dir.display()))?;
+    let path = dir.join(format!("{}.yml", run.id));
+    debug!("Writing journal entry: {}", path.display());
+    fs::write(&path, serde_yaml::to_string(run)?)
+        .with_context(|| format!("Failed to write journal entry: {}", path.display()))
+}
+
+/// Moves references back to earlier values, then checks out the restored HEAD.
+///
+/// Refuses to touch a checked-out branch while its working tree has changes to tracked
+/// files; untracked files such as build artifacts are left alone.
+pub(crate) fn restore_refs(repo: &Repository, moves: &[RefMove], message: &str) -> Result<()> {
+    let head_moves = moves.iter().any(|m| moves_head(repo, &m.name));
+    if head_moves && has_changes(repo, true)? {
+        return Err(GitRebrandError::InvalidRepository(
+            "Repository has uncommitted changes".to_string(),
+        )
+        .into());
+    }
+    let names: Vec<&str> = moves.iter().map(|m| m.name.as_str()).collect();
+    let checkouts = worktree::other_checkouts(repo)?;
+    worktree::ensure_clean(&checkouts, &names, true)?;
+
+    apply_ref_moves(repo, moves, message)?;
+    if head_moves && !repo.is_bare() {
+        let head = repo.head()?.peel_to_commit()?;
+        repo.reset(head.as_object(), git2::ResetType::Hard, None)?;
+    }
+    worktree::sync(&checkouts, &names)
+}
+
+/// Points every named reference at its new object within a single transaction.
+///
+/// Each reference is checked against its expected value once locked, so a ref moved by
+/// another process in the meantime aborts the transaction instead of being overwritten.
+pub(crate) fn apply_ref_moves(repo: &Repository, moves: &[RefMove], message: &str) -> Result<()> {
+    let mut transaction = repo.transaction()?;
+    for m in moves {
+        transaction.lock_ref(&m.name)?;
+    }
+    for m in moves {
+        let Some(expected) = m.expected else {
+            continue;
+        };
+        let current = repo.refname_to_id(&m.name).ok();
+        if current != Some(expected) {
+            return Err(GitRebrandError::InvalidRepository(format!(
+                "{} moved to {} while it was being rewritten (expected {})",
+                m.name,
+                current.map_or_else(|| "nothing".to_string(), |id| id.to_string()),
+                expected
+            ))
+            .into());
+        }
+    }
+    for m in moves {
+        debug!("Moving {} to {}", m.name, m.target);
+        transaction.set_target(&m.name, m.target, None, message)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+// Journal entries are shared by all worktrees of a repository
+fn runs_dir(repo: &Repository) -> PathBuf {
+    common_dir(repo).join("rebrand").join("runs")
+}
+
+fn load_runs(repo: &Repository) -> Result<Vec<RunRecord>> {
+    let dir = runs_dir(repo);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("yml") {
+            continue;
+        }
+        let content = fs::read_to_string(&path)?;
+        let run: RunRecord = serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid journal entry: {}", path.display()))?;
+        runs.push(run);
+    }
+    runs.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+    Ok(runs)
+}
+
+fn open(path: &Path) -> Result<Repository> {
+    Repository::open(path).context("Failed to open git repository")
+}
+
+// Whether moving the reference changes what is checked out
+fn moves_head(repo: &Repository, name: &str) -> bool {
+    name == "HEAD"
+        || repo
+            .head()
+            .ok()
+            .and_then(|head| head.name().map(|head| head == name))
+            .unwrap_or(false)
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_moved_ref_aborts_transaction() -> Result<()> {
+        let temp = TempDir::new()?;
+        let repo = Repository::init(temp.path())?;
+        let sig = Signature::now("Test User", "test@example.com")?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+        let first = repo.commit(Some("refs/heads/a"), &sig, &sig, "First", &tree, &[])?;
+        let second = repo.commit(None, &sig, &sig, "Second", &tree, &[])?;
+        repo.reference("refs/heads/b", second, false, "test")?;
+
+        // `refs/heads/b` no longer points where the caller read it
+        let moves = [
+            RefMove {
+                name: "refs/heads/a".to_string(),
+                expected: Some(first),
+                target: second,
+            },
+            RefMove {
+                name: "refs/heads/b".to_string(),
+                expected: Some(first),
+                target: first,
+            },
+        ];
+        assert!(apply_ref_moves(&repo, &moves, "test").is_err());
+        assert_eq!(repo.refname_to_id("refs/heads/a")?, first);
+        assert_eq!(repo.refname_to_id("refs/heads/b")?, second);
+
+        apply_ref_moves(&repo, &moves[..1], "test")?;
+        assert_eq!(repo.refname_to_id("refs/heads/a")?, second);
+        Ok(())
+    }
+}
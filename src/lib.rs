@@ -1,17 +1,38 @@
 use anyhow::{Context, Result};
-use chrono::Local;
+use chrono::{DateTime, SecondsFormat};
 use dialoguer::{theme::ColorfulTheme, Input};
-use git2::{Repository, Signature};
-use log::{debug, error, info, warn};
+use git2::{Oid, Repository, Signature};
+use log::{debug, info, warn};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 use thiserror::Error;
 
+pub mod backup;
+pub mod journal;
+pub mod mailmap;
+mod pattern;
+mod replace;
+pub mod report;
+mod trailers;
+mod tree;
+mod window;
+mod worktree;
+
+pub use pattern::Pattern;
+use pattern::{CompiledMapping, RuleMatch};
+use replace::Replacer;
+pub use replace::TextReplacement;
+pub use trailers::{TrailerHit, DEFAULT_TRAILER_KEYS};
+pub use tree::PathRename;
+use tree::{PathGlobs, TreeRewriter, TreeRules};
+use window::CommitWindow;
+pub use worktree::DirtyPolicy;
+
 // Error definitions
 #[derive(Error, Debug)]
 pub enum GitRebrandError {
@@ -29,33 +50,272 @@ pub enum GitRebrandError {
 
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
+
+    #[error("Path rename conflict: {0}")]
+    PathConflict(String),
+
+    #[error("Failed to undo rewrite: {0}")]
+    UndoFailed(String),
+
+    #[error("Invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error("History was rewritten, but stashed changes could not be reapplied: {0}")]
+    StashConflict(String),
+}
+
+// Which identity of a commit matched the configured patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchSide {
+    Author,
+    Committer,
+    Both,
+    /// Only the commit message (e.g. its trailers) is affected
+    Neither,
+}
+
+impl MatchSide {
+    fn from_matches(author: bool, committer: bool) -> Self {
+        match (author, committer) {
+            (true, true) => Self::Both,
+            (true, false) => Self::Author,
+            (false, true) => Self::Committer,
+            (false, false) => Self::Neither,
+        }
+    }
+
+    pub fn includes_author(self) -> bool {
+        matches!(self, Self::Author | Self::Both)
+    }
+
+    pub fn includes_committer(self) -> bool {
+        matches!(self, Self::Committer | Self::Both)
+    }
+}
+
+impl std::fmt::Display for MatchSide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Author => write!(f, "author"),
+            Self::Committer => write!(f, "committer"),
+            Self::Both => write!(f, "author+committer"),
+            Self::Neither => write!(f, "none"),
+        }
+    }
 }
 
 // Commit information structure
 #[derive(Debug, Clone)]
 pub struct CommitInfo {
-    pub id: String,
+    pub id: Oid,
+    pub short_id: String,
     pub author: String,
-    pub timestamp: String,
+    pub committer: String,
+    pub new_author: String,
+    pub new_committer: String,
+    /// Commit time in seconds since the Unix epoch
+    pub timestamp: i64,
+    /// First line of the original commit message
+    pub subject: String,
+    pub matched_pattern: String,
+    pub matched_side: MatchSide,
+    /// Labels of the mappings that rewrite the author and committer
+    pub mappings: Vec<String>,
+    /// Identities rewritten in trailers such as `Co-authored-by:`
+    pub trailer_hits: Vec<TrailerHit>,
+    /// Original and rewritten message, when the message changes
+    pub message_change: Option<MessageChange>,
+    /// Whether content replacement rules change files in the commit's tree
+    pub tree_changed: bool,
+    /// Changed paths that matched the configured path scope
+    pub matched_paths: Vec<String>,
+}
+
+impl CommitInfo {
+    /// Commit time as an RFC 3339 timestamp in UTC
+    pub fn iso_timestamp(&self) -> String {
+        DateTime::from_timestamp(self.timestamp, 0)
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_else(|| self.timestamp.to_string())
+    }
+}
+
+// Annotated tag in the rewritten history whose tagger matches a rule
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    /// Full reference name, e.g. `refs/tags/v1.0`
+    pub name: String,
+    pub tagger: String,
+    pub new_tagger: String,
     pub matched_pattern: String,
 }
 
+// Outcome of a dry run: the commits and tags a rewrite would change
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub commits: Vec<CommitInfo>,
+    pub tags: Vec<TagInfo>,
+}
+
+// Outcome of a rewrite: the journal record of the run and the commits and tags it rewrote
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    pub run: journal::RunRecord,
+    pub commits: Vec<CommitInfo>,
+    pub tags: Vec<TagInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageChange {
+    pub before: String,
+    pub after: String,
+}
+
+impl MessageChange {
+    /// Line-by-line preview of the lines that differ
+    pub fn preview(&self) -> Vec<String> {
+        let before: Vec<_> = self.before.lines().collect();
+        let after: Vec<_> = self.after.lines().collect();
+        let mut preview = Vec::new();
+        for i in 0..before.len().max(after.len()) {
+            let (old, new) = (before.get(i), after.get(i));
+            if old == new {
+                continue;
+            }
+            if let Some(old) = old {
+                preview.push(format!("- {}", old));
+            }
+            if let Some(new) = new {
+                preview.push(format!("+ {}", new));
+            }
+        }
+        preview
+    }
+}
+
+// Result of rewriting a commit message
+struct MessageRewrite {
+    message: String,
+    trailer_hits: Vec<TrailerHit>,
+    changed: bool,
+}
+
+// Replacement identity together with the patterns that select it.
+// An empty name or email keeps the original value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityMapping {
+    pub new_name: String,
+    pub new_email: String,
+    pub patterns: Vec<Pattern>,
+}
+
 // Configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub new_author_name: String,
+    #[serde(default)]
     pub new_author_email: String,
-    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<Pattern>,
+    /// Additional identity mappings, tried in order before the top-level rule
+    #[serde(default)]
+    pub mappings: Vec<IdentityMapping>,
+    /// `.mailmap` file whose entries are applied after `mappings`
+    #[serde(default)]
+    pub mailmap: Option<PathBuf>,
     #[serde(default)]
     pub repo_path: PathBuf,
     #[serde(default = "default_backup")]
     pub create_backup: bool,
+    /// Also write a `git bundle` of the original refs to this file or directory before rewriting
+    #[serde(default)]
+    pub backup_bundle: Option<PathBuf>,
+    /// How uncommitted changes in the working tree are handled
+    #[serde(default)]
+    pub dirty_policy: DirtyPolicy,
+    /// Stamp rewritten commits with the current time instead of their original dates
+    #[serde(default)]
+    pub redate: bool,
+    /// Separate rule for committer identities; when absent the author rule applies to both
+    #[serde(default)]
+    pub committer: Option<IdentityMapping>,
+    /// Reference globs to rewrite (e.g. `refs/heads/*`); only HEAD is rewritten when empty
+    #[serde(default)]
+    pub refs: Vec<String>,
+    /// Message trailers whose identities are rewritten with the author rules
+    #[serde(default = "default_trailer_keys")]
+    pub trailer_keys: Vec<String>,
+    /// Text substitutions applied to every rewritten commit message
+    #[serde(default)]
+    pub message_replacements: Vec<TextReplacement>,
+    /// Text substitutions applied to tracked files throughout history
+    #[serde(default)]
+    pub content_replacements: Vec<TextReplacement>,
+    /// Path globs limiting which files content replacements apply to (all files when empty)
+    #[serde(default)]
+    pub content_include: Vec<String>,
+    /// Path globs excluded from content replacements
+    #[serde(default)]
+    pub content_exclude: Vec<String>,
+    /// Files and directories renamed in every rewritten tree
+    #[serde(default)]
+    pub path_renames: Vec<PathRename>,
+    /// Revision range (`v1.0..HEAD`) limiting which commits are rewritten
+    #[serde(default)]
+    pub range: Option<String>,
+    /// Only rewrite commits made on or after this date (`YYYY-MM-DD` or RFC 3339)
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Only rewrite commits made on or before this date (`YYYY-MM-DD` or RFC 3339)
+    #[serde(default)]
+    pub until: Option<String>,
+    /// Only rewrite descendants of this commit
+    #[serde(default)]
+    pub after_commit: Option<String>,
+    /// Path globs a commit must touch, compared with its first parent, to be rewritten
+    #[serde(default)]
+    pub paths: Vec<String>,
 }
 
 fn default_backup() -> bool {
     true
 }
 
+fn default_trailer_keys() -> Vec<String> {
+    DEFAULT_TRAILER_KEYS.iter().map(|k| k.to_string()).collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            new_author_name: String::new(),
+            new_author_email: String::new(),
+            patterns: Vec::new(),
+            mappings: Vec::new(),
+            mailmap: None,
+            repo_path: PathBuf::new(),
+            create_backup: default_backup(),
+            backup_bundle: None,
+            dirty_policy: DirtyPolicy::default(),
+            redate: false,
+            committer: None,
+            refs: Vec::new(),
+            trailer_keys: default_trailer_keys(),
+            message_replacements: Vec::new(),
+            content_replacements: Vec::new(),
+            content_include: Vec::new(),
+            content_exclude: Vec::new(),
+            path_renames: Vec::new(),
+            range: None,
+            since: None,
+            until: None,
+            after_commit: None,
+            paths: Vec::new(),
+        }
+    }
+}
+
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path).context("Failed to read configuration file")?;
@@ -66,14 +326,44 @@ impl Config {
         let email_regex = Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$")
             .context("Failed to compile email regex")?;
 
-        if !email_regex.is_match(&config.new_author_email) {
-            return Err(GitRebrandError::InvalidConfig(format!(
-                "Invalid email format: {}",
-                config.new_author_email
-            ))
+        if config.patterns.is_empty()
+            && config.mappings.is_empty()
+            && config.committer.is_none()
+            && config.mailmap.is_none()
+        {
+            return Err(GitRebrandError::InvalidConfig(
+                "At least one pattern, mapping, committer rule or mailmap must be configured"
+                    .to_string(),
+            )
             .into());
         }
 
+        let top_level_email =
+            Some(&config.new_author_email).filter(|_| !config.patterns.is_empty());
+        let mapping_emails = config
+            .mappings
+            .iter()
+            .map(|m| &m.new_email)
+            .filter(|email| !email.is_empty());
+        let committer_email = config
+            .committer
+            .as_ref()
+            .map(|c| &c.new_email)
+            .filter(|email| !email.is_empty());
+        for email in top_level_email
+            .into_iter()
+            .chain(mapping_emails)
+            .chain(committer_email)
+        {
+            if !email_regex.is_match(email) {
+                return Err(GitRebrandError::InvalidConfig(format!(
+                    "Invalid email format: {}",
+                    email
+                ))
+                .into());
+            }
+        }
+
         Ok(config)
     }
 
@@ -116,7 +406,7 @@ impl Config {
         while stdin.read_line(&mut input)? > 0 {
             let pattern = input.trim().to_string();
             if !pattern.is_empty() {
-                patterns.push(pattern);
+                patterns.push(Pattern::Plain(pattern));
             }
             input.clear();
         }
@@ -132,15 +422,35 @@ impl Config {
             new_author_name,
             new_author_email,
             patterns,
-            repo_path: PathBuf::new(),
-            create_backup: true,
+            ..Default::default()
         })
     }
 }
 
+// A reference selected for rewriting, the object it points at and the commit behind it
+#[derive(Debug, Clone)]
+struct SelectedRef {
+    name: String,
+    object: Oid,
+    target: Oid,
+}
+
+// A reference move computed by the rewrite, applied once all objects are written
+#[derive(Debug, Clone)]
+struct RefUpdate {
+    name: String,
+    old: Oid,
+    new: Oid,
+}
+
 pub struct GitRebrander {
     repo: Repository,
     config: Config,
+    author_rules: Vec<CompiledMapping>,
+    committer_rules: Option<Vec<CompiledMapping>>,
+    message_replacer: Replacer,
+    tree_rules: TreeRules,
+    path_scope: PathGlobs,
 }
 
 impl std::fmt::Debug for GitRebrander {
@@ -153,13 +463,53 @@ impl std::fmt::Debug for GitRebrander {
 
 impl GitRebrander {
     pub fn new(config: Config) -> Result<Self> {
-        let repo = Repository::open(&config.repo_path).context("Failed to open git repository")?;
-        let rebrander = Self { repo, config };
+        let rebrander = Self::open(config)?;
         // Validate repository immediately upon creation
         rebrander.validate_repository()?;
         Ok(rebrander)
     }
 
+    /// Opens the repository and compiles the rules without validating the working tree,
+    /// for read-only operations such as [`GitRebrander::export_mailmap`]
+    pub fn open(config: Config) -> Result<Self> {
+        let repo = Repository::open(&config.repo_path).context("Failed to open git repository")?;
+        let mailmap_entries = match &config.mailmap {
+            Some(path) => mailmap::from_file(path)?,
+            None => Vec::new(),
+        };
+        let mut author_rules = config
+            .mappings
+            .iter()
+            .chain(&mailmap_entries)
+            .map(|m| CompiledMapping::new(&m.new_name, &m.new_email, &m.patterns))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !config.patterns.is_empty() {
+            author_rules.push(CompiledMapping::new(
+                &config.new_author_name,
+                &config.new_author_email,
+                &config.patterns,
+            )?);
+        }
+        let committer_rules = config
+            .committer
+            .as_ref()
+            .map(|m| CompiledMapping::new(&m.new_name, &m.new_email, &m.patterns).map(|r| vec![r]))
+            .transpose()?;
+        let message_replacer = Replacer::new(&config.message_replacements)?;
+        let tree_rules = TreeRules::new(&config)?;
+        let path_scope = PathGlobs::new(&config.paths)?;
+        let rebrander = Self {
+            repo,
+            config,
+            author_rules,
+            committer_rules,
+            message_replacer,
+            tree_rules,
+            path_scope,
+        };
+        Ok(rebrander)
+    }
+
     fn validate_repository(&self) -> Result<()> {
         // Check if repository is empty
         if self.repo.is_empty()? {
@@ -168,17 +518,16 @@ impl GitRebrander {
             );
         }
 
-        // Check for bare repository
+        // Bare repositories have no working tree to keep in sync, only refs
         if self.repo.is_bare() {
-            return Err(GitRebrandError::InvalidRepository(
-                "Bare repositories are not supported".to_string(),
-            )
-            .into());
+            debug!("Bare repository, skipping working tree checks");
+            return Ok(());
         }
 
-        // Check for uncommitted changes
-        let statuses = self.repo.statuses(None)?;
-        if statuses.iter().any(|s| s.status() != git2::Status::CURRENT) {
+        // Check for uncommitted changes; auto-stash sets them aside during the rewrite instead
+        let policy = self.config.dirty_policy;
+        let tracked_only = policy == DirtyPolicy::IgnoreUntracked;
+        if policy != DirtyPolicy::AutoStash && worktree::has_changes(&self.repo, tracked_only)? {
             return Err(GitRebrandError::InvalidRepository(
                 "Repository has uncommitted changes".to_string(),
             )
@@ -189,140 +538,849 @@ impl GitRebrander {
     }
 
     pub fn dry_run(&self) -> Result<Vec<CommitInfo>> {
+        Ok(self.preview()?.commits)
+    }
+
+    /// Dry run that also reports the annotated tags whose tagger would be rebranded
+    pub fn preview(&self) -> Result<Preview> {
         info!("Starting dry run analysis");
         self.validate_repository()?;
 
         let mut affected_commits = Vec::new();
+        // Rewritten trees are only computed in memory during a dry run
+        let scratch = self.scratch_repo()?;
+        let mut tree_rewriter = self.tree_rewriter(&scratch);
+        let refs = self.selected_refs()?;
+        // Branches checked out in other worktrees move with the rewrite
+        let names: Vec<&str> = refs.iter().map(|r| r.name.as_str()).collect();
+        let tracked_only = self.config.dirty_policy != DirtyPolicy::Strict;
+        worktree::ensure_clean(
+            &worktree::other_checkouts(&self.repo)?,
+            &names,
+            tracked_only,
+        )?;
+
         let mut revwalk = self.repo.revwalk()?;
-        revwalk.push_head()?;
+        for selected in &refs {
+            revwalk.push(selected.target)?;
+        }
         revwalk.set_sorting(git2::Sort::TIME)?;
+        let tips: Vec<Oid> = refs.iter().map(|r| r.target).collect();
+        let window = CommitWindow::new(&self.repo, &self.config, &tips)?;
+        let mut walked = HashSet::new();
 
         for oid in revwalk {
             let oid = oid?;
+            walked.insert(oid);
             let commit = self.repo.find_commit(oid)?;
+            if !window.contains(&commit) {
+                continue;
+            }
+            // The path scope limits identity and message rewriting; file contents and
+            // paths are rewritten in every commit so the brand stays consistent
+            let matched_paths = self.scoped_paths(&commit)?;
+            let in_scope = self.path_scope.is_empty() || !matched_paths.is_empty();
             let author = commit.author();
+            let committer = commit.committer();
 
-            if let Some(pattern) =
-                self.matches_pattern(author.name().unwrap_or(""), author.email().unwrap_or(""))
-            {
-                affected_commits.push(CommitInfo {
-                    id: oid.to_string()[..8].to_string(),
-                    author: format!(
-                        "{} <{}>",
-                        author.name().unwrap_or(""),
-                        author.email().unwrap_or("")
-                    ),
-                    timestamp: commit.time().seconds().to_string(),
-                    matched_pattern: pattern,
-                });
+            let author_match = self.match_author(&author).filter(|_| in_scope);
+            let committer_match = self.match_committer(&committer).filter(|_| in_scope);
+            let MessageRewrite {
+                message,
+                trailer_hits,
+                changed,
+            } = if in_scope {
+                self.new_message(&commit)
+            } else {
+                MessageRewrite {
+                    message: String::new(),
+                    trailer_hits: Vec::new(),
+                    changed: false,
+                }
+            };
+            let side = MatchSide::from_matches(author_match.is_some(), committer_match.is_some());
+            let new_tree = tree_rewriter
+                .rewrite(&commit.tree()?)
+                .with_context(|| format!("Failed to rewrite the tree of commit {}", oid))?;
+            let tree_changed = new_tree != commit.tree_id();
+            if side == MatchSide::Neither && !changed && !tree_changed {
+                continue;
+            }
+
+            let new_author = match &author_match {
+                Some(m) => target_identity(m.mapping, &author),
+                None => format_identity(&author),
+            };
+            let new_committer = match &committer_match {
+                Some(m) => target_identity(m.mapping, &committer),
+                None => format_identity(&committer),
+            };
+            let mut mappings = Vec::new();
+            for rule in author_match.iter().chain(&committer_match) {
+                if !mappings.contains(&rule.mapping.label) {
+                    mappings.push(rule.mapping.label.clone());
+                }
+            }
+
+            debug!("Commit {} matched on {}", oid, side);
+            if !matched_paths.is_empty() {
+                debug!("  touches {}", matched_paths.join(", "));
             }
+            let message_change = changed.then(|| MessageChange {
+                before: String::from_utf8_lossy(commit.message_raw_bytes()).into_owned(),
+                after: message,
+            });
+            if let Some(change) = &message_change {
+                info!("Message of {} changes:", short_id(&commit)?);
+                for line in change.preview() {
+                    info!("  {}", line);
+                }
+            }
+            affected_commits.push(CommitInfo {
+                id: oid,
+                short_id: short_id(&commit)?,
+                author: format_identity(&author),
+                committer: format_identity(&committer),
+                new_author,
+                new_committer,
+                timestamp: commit.time().seconds(),
+                subject: commit.summary().unwrap_or_default().to_string(),
+                matched_pattern: author_match
+                    .or(committer_match)
+                    .map(|m| m.pattern)
+                    .or_else(|| trailer_hits.first().map(|h| h.matched_pattern.clone()))
+                    .unwrap_or_default(),
+                matched_side: side,
+                mappings,
+                trailer_hits,
+                message_change,
+                tree_changed,
+                matched_paths,
+            });
         }
 
-        if affected_commits.is_empty() {
+        let tags = self.matching_taggers(&walked)?;
+        if affected_commits.is_empty() && tags.is_empty() {
             return Err(GitRebrandError::NoMatchingCommits.into());
         }
 
+        let summary = report::Summary::new(&affected_commits, &tags, None);
         info!(
-            "Dry run complete. Found {} affected commits",
-            affected_commits.len()
+            "Dry run complete. Found {} affected commits ({} trailer identities, {} with file changes)",
+            summary.commits,
+            summary.trailer_identities,
+            summary.tree_changes
         );
-        Ok(affected_commits)
+        for (mapping, count) in &summary.mappings {
+            info!("  {} commits -> {}", count, mapping);
+        }
+        for tag in &tags {
+            info!(
+                "  tagger of {}: {} -> {}",
+                tag.name, tag.tagger, tag.new_tagger
+            );
+        }
+        Ok(Preview {
+            commits: affected_commits,
+            tags,
+        })
+    }
+
+    // Annotated tags on walked commits whose tagger matches an author rule
+    fn matching_taggers(&self, walked: &HashSet<Oid>) -> Result<Vec<TagInfo>> {
+        let mut tags = Vec::new();
+        for reference in self.repo.references_glob("refs/tags/*")? {
+            let reference = reference?;
+            let (Some(name), Ok(tag)) = (reference.name(), reference.peel_to_tag()) else {
+                continue;
+            };
+            let Ok(target) = reference.peel_to_commit() else {
+                continue;
+            };
+            let Some(tagger) = tag.tagger() else {
+                continue;
+            };
+            if !walked.contains(&target.id()) {
+                continue;
+            }
+            if let Some(rule) = self.match_author(&tagger) {
+                tags.push(TagInfo {
+                    name: name.to_string(),
+                    tagger: format_identity(&tagger),
+                    new_tagger: target_identity(rule.mapping, &tagger),
+                    matched_pattern: rule.pattern,
+                });
+            }
+        }
+        Ok(tags)
     }
 
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self) -> Result<RunReport> {
         // First do a dry run to validate everything
-        let affected_commits = self.dry_run()?;
+        let Preview {
+            commits: affected_commits,
+            tags,
+        } = self.preview()?;
 
-        // Create backup if needed
+        // Write the rewritten objects; no reference moves until backups exist
+        let run_id = journal::new_run_id(&self.repo)?;
+        let updates = self
+            .rewrite_history(&affected_commits)
+            .context("Failed to rewrite history")?;
+
+        let originals: Vec<_> = updates.iter().map(|u| (u.name.clone(), u.old)).collect();
         if self.config.create_backup {
-            self.create_backup().context("Failed to create backup")?;
+            backup::create(&self.repo, &run_id, &originals).context("Failed to create backup")?;
         }
-
-        // Perform the rewrite
-        self.rewrite_history(&affected_commits)
-            .context("Failed to rewrite history")?;
+        if let Some(path) = &self.config.backup_bundle {
+            backup::write_bundle(&self.repo, path, &run_id, &originals)
+                .context("Failed to write backup bundle")?;
+        }
+        let stashed = self.stash_changes()?;
+        let result = self.update_refs(&updates, &run_id);
+        // Stashed changes are reapplied whether or not the refs moved; a failed rewrite
+        // is reported ahead of a failed reapply
+        let reapplied = if stashed {
+            self.reapply_stash()
+        } else {
+            Ok(())
+        };
+        let run = result?;
+        reapplied?;
 
         info!("Successfully rewrote {} commits", affected_commits.len());
-        Ok(())
+        info!(
+            "Recorded as run {}; undo with `git rebrand undo {}`",
+            run_id, run_id
+        );
+        Ok(RunReport {
+            run,
+            commits: affected_commits,
+            tags,
+        })
     }
 
-    fn create_backup(&self) -> Result<()> {
-        let head = self.repo.head()?;
-        let backup_name = format!("backup_{}", Local::now().format("%Y%m%d%H%M%S"));
+    // Stashes local changes, untracked files included, when the auto-stash policy is set.
+    // Returns whether anything was stashed.
+    fn stash_changes(&self) -> Result<bool> {
+        if self.config.dirty_policy != DirtyPolicy::AutoStash
+            || !worktree::has_changes(&self.repo, false)?
+        {
+            return Ok(false);
+        }
+        let mut repo = Repository::open(self.repo.path())?;
+        let stasher = repo
+            .signature()
+            .or_else(|_| Signature::now("git-rebrand", "git-rebrand@localhost"))?;
+        let stashed = repo.stash_save(
+            &stasher,
+            "rebrand: auto-stash",
+            Some(git2::StashFlags::INCLUDE_UNTRACKED),
+        );
+        match stashed {
+            Ok(stash) => {
+                info!("Stashed local changes as {}", stash);
+                Ok(true)
+            },
+            // Only ignored files were reported; they are never stashed or touched
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Applies the auto-stash on top of the rewritten HEAD. On conflicts the stash is kept
+    // and the conflicting paths are reported.
+    fn reapply_stash(&self) -> Result<()> {
+        let mut repo = Repository::open(self.repo.path())?;
+        let applied = repo.stash_apply(0, None);
 
-        debug!("Creating backup branch: {}", backup_name);
-        self.repo
-            .branch(&backup_name, &head.peel_to_commit()?, false)?;
+        let index = repo.index()?;
+        let mut conflicts = BTreeSet::new();
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+            if let Some(entry) = entry {
+                conflicts.insert(String::from_utf8_lossy(&entry.path).into_owned());
+            }
+        }
+        if !conflicts.is_empty() {
+            let conflicts: Vec<_> = conflicts.into_iter().collect();
+            return Err(GitRebrandError::StashConflict(format!(
+                "conflicts in {}; the changes are kept in stash@{{0}}",
+                conflicts.join(", ")
+            ))
+            .into());
+        }
+        // Failures without conflicting index entries, e.g. untracked files in the way
+        if let Err(e) = applied {
+            return Err(GitRebrandError::StashConflict(format!(
+                "the changes could not be reapplied ({}); they are kept in stash@{{0}}",
+                e.message()
+            ))
+            .into());
+        }
 
-        info!("Created backup branch: {}", backup_name);
+        repo.stash_drop(0)?;
+        info!("Reapplied stashed changes");
         Ok(())
     }
 
-    fn matches_pattern(&self, author: &str, email: &str) -> Option<String> {
-        let author_lower = author.to_lowercase();
-        let email_lower = email.to_lowercase();
+    // Paths changed relative to the first parent that fall within the configured scope
+    fn scoped_paths(&self, commit: &git2::Commit) -> Result<Vec<String>> {
+        if self.path_scope.is_empty() {
+            return Ok(Vec::new());
+        }
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff =
+            self.repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
 
-        for pattern in &self.config.patterns {
-            let pattern_lower = pattern.to_lowercase();
-            if pattern_lower.contains('@') {
-                if email_lower.contains(&pattern_lower) {
-                    return Some(pattern.clone());
+        let mut paths = BTreeSet::new();
+        for delta in diff.deltas() {
+            for file in [delta.old_file(), delta.new_file()] {
+                let Some(path) = file.path().and_then(Path::to_str) else {
+                    continue;
+                };
+                if self.path_scope.is_match(path) {
+                    paths.insert(path.to_string());
                 }
-            } else if author_lower.contains(&pattern_lower) {
-                return Some(pattern.clone());
             }
         }
-        None
+        Ok(paths.into_iter().collect())
+    }
+
+    // Repository handle whose object writes stay in memory, used to preview tree rewrites
+    fn scratch_repo(&self) -> Result<Repository> {
+        let scratch = Repository::open(self.repo.path())?;
+        scratch.odb()?.add_new_mempack_backend(1000)?;
+        Ok(scratch)
     }
 
-    fn rewrite_history(&self, commits: &[CommitInfo]) -> Result<()> {
+    fn tree_rewriter<'r>(&'r self, repo: &'r Repository) -> TreeRewriter<'r> {
+        TreeRewriter::new(repo, &self.tree_rules)
+    }
+
+    /// Renders a `.mailmap` covering every identity in the selected history that the
+    /// configuration would rewrite, so it can be published instead of rewriting
+    pub fn export_mailmap(&self) -> Result<String> {
+        let refs = self.selected_refs()?;
+        let mut revwalk = self.repo.revwalk()?;
+        for selected in &refs {
+            revwalk.push(selected.target)?;
+        }
+
+        let mut entries = BTreeSet::new();
+        for oid in revwalk {
+            let commit = self.repo.find_commit(oid?)?;
+            let author = commit.author();
+            let committer = commit.committer();
+            let matches = [
+                (self.match_author(&author), &author),
+                (self.match_committer(&committer), &committer),
+            ];
+            for (rule, signature) in matches {
+                let Some(rule) = rule else {
+                    continue;
+                };
+                let name = signature.name().unwrap_or("");
+                let email = signature.email().unwrap_or("");
+                let (new_name, new_email) = rule.mapping.target(name, email);
+                if (new_name, new_email) != (name, email) {
+                    entries.insert(mailmap::format_entry(new_name, new_email, name, email));
+                }
+            }
+        }
+
+        let mut output = String::from("# Generated by git-rebrand\n");
+        for entry in entries {
+            output.push_str(&entry);
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    /// Resolves the configured reference globs, falling back to HEAD when none are given
+    fn selected_refs(&self) -> Result<Vec<SelectedRef>> {
+        if self.config.refs.is_empty() {
+            let head = self.repo.head()?;
+            let name = if head.is_branch() {
+                head.name().unwrap_or("HEAD").to_string()
+            } else {
+                "HEAD".to_string()
+            };
+            let target = head.peel_to_commit()?.id();
+            return Ok(vec![SelectedRef {
+                name,
+                object: target,
+                target,
+            }]);
+        }
+
+        let mut selected: BTreeMap<String, SelectedRef> = BTreeMap::new();
+        for glob in &self.config.refs {
+            let references = self
+                .repo
+                .references_glob(glob)
+                .with_context(|| format!("Invalid reference glob: {}", glob))?;
+            for reference in references {
+                let reference = reference?;
+                let Some(name) = reference.name() else {
+                    continue;
+                };
+                if reference.symbolic_target().is_some() || is_internal_ref(name) {
+                    continue;
+                }
+                let Some(object) = reference.target() else {
+                    continue;
+                };
+                match reference.peel_to_commit() {
+                    Ok(commit) => {
+                        selected.insert(
+                            name.to_string(),
+                            SelectedRef {
+                                name: name.to_string(),
+                                object,
+                                target: commit.id(),
+                            },
+                        );
+                    },
+                    Err(_) => debug!("Skipping non-commit reference: {}", name),
+                }
+            }
+        }
+
+        if selected.is_empty() {
+            return Err(GitRebrandError::InvalidConfig(format!(
+                "No references matched: {}",
+                self.config.refs.join(", ")
+            ))
+            .into());
+        }
+
+        Ok(selected.into_values().collect())
+    }
+
+    /// Computes the new value of every selected reference and of every tag in the walked
+    /// history, recreating annotated tags whose target or tagger changes
+    fn ref_updates(
+        &self,
+        refs: &[SelectedRef],
+        walked: &HashSet<Oid>,
+        rewritten: &HashMap<Oid, Oid>,
+    ) -> Result<Vec<RefUpdate>> {
+        let mut rewritten_tags = HashMap::new();
+        let mut updates = Vec::new();
+
+        for selected in refs {
+            let new = if selected.object == selected.target {
+                rewritten.get(&selected.target).copied()
+            } else {
+                self.rewrite_tag(selected.object, rewritten, &mut rewritten_tags)?
+            };
+            if let Some(new) = new {
+                updates.push(RefUpdate {
+                    name: selected.name.clone(),
+                    old: selected.object,
+                    new,
+                });
+            }
+        }
+
+        // Tags in the rewritten history follow their commits and get their taggers
+        // rebranded even when no glob selected them
+        let selected: HashSet<&str> = refs.iter().map(|r| r.name.as_str()).collect();
+        for reference in self.repo.references_glob("refs/tags/*")? {
+            let reference = reference?;
+            let (Some(name), Some(object)) = (reference.name(), reference.target()) else {
+                continue;
+            };
+            let Ok(target) = reference.peel_to_commit().map(|commit| commit.id()) else {
+                continue;
+            };
+            if selected.contains(name) || !walked.contains(&target) {
+                continue;
+            }
+            let new = if object == target {
+                rewritten.get(&target).copied()
+            } else {
+                self.rewrite_tag(object, rewritten, &mut rewritten_tags)?
+            };
+            if let Some(new) = new {
+                updates.push(RefUpdate {
+                    name: name.to_string(),
+                    old: object,
+                    new,
+                });
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Recreates an annotated tag pointing at the rewritten target, rebranding its tagger.
+    /// Returns `None` when neither the target nor the tagger changed.
+    fn rewrite_tag(
+        &self,
+        tag_id: Oid,
+        rewritten: &HashMap<Oid, Oid>,
+        rewritten_tags: &mut HashMap<Oid, Option<Oid>>,
+    ) -> Result<Option<Oid>> {
+        if let Some(new_id) = rewritten_tags.get(&tag_id) {
+            return Ok(*new_id);
+        }
+
+        let tag = self.repo.find_tag(tag_id)?;
+        let new_target = match tag.target_type() {
+            Some(git2::ObjectType::Tag) => {
+                self.rewrite_tag(tag.target_id(), rewritten, rewritten_tags)?
+            },
+            _ => rewritten.get(&tag.target_id()).copied(),
+        };
+
+        let tagger = tag.tagger();
+        let tagger_match = tagger.as_ref().and_then(|t| self.match_author(t));
+
+        let new_id = if new_target.is_none() && tagger_match.is_none() {
+            None
+        } else {
+            let new_tagger = match (&tagger, &tagger_match) {
+                (Some(original), Some(rule)) => {
+                    debug!(
+                        "Rebranding tagger of {} (matched {})",
+                        tag.name().unwrap_or(""),
+                        rule.pattern
+                    );
+                    Some(self.new_signature(rule.mapping, original)?)
+                },
+                (original, _) => original.as_ref().map(|t| t.to_owned()),
+            };
+            let target = new_target.unwrap_or_else(|| tag.target_id());
+            Some(self.write_tag(&tag, target, new_tagger.as_ref())?)
+        };
+
+        rewritten_tags.insert(tag_id, new_id);
+        Ok(new_id)
+    }
+
+    // Writes the tag object directly so tags without a tagger survive the rewrite
+    fn write_tag(&self, tag: &git2::Tag, target: Oid, tagger: Option<&Signature>) -> Result<Oid> {
+        let kind = tag.target_type().unwrap_or(git2::ObjectType::Commit);
+        let mut buffer = format!(
+            "object {}\ntype {}\ntag {}\n",
+            target,
+            kind.str(),
+            String::from_utf8_lossy(tag.name_bytes())
+        )
+        .into_bytes();
+        if let Some(tagger) = tagger {
+            buffer.extend_from_slice(b"tagger ");
+            buffer.extend_from_slice(&format_raw_signature(tagger));
+            buffer.push(b'\n');
+        }
+        buffer.push(b'\n');
+        buffer.extend_from_slice(tag.message_bytes().unwrap_or_default());
+
+        Ok(self.repo.odb()?.write(git2::ObjectType::Tag, &buffer)?)
+    }
+
+    /// Writes the commit object directly so the original message bytes and extra headers
+    /// (`encoding`, `mergetag`, ...) survive the rewrite. `message` replaces the original
+    /// message when a rule changed it.
+    fn write_commit(
+        &self,
+        commit: &git2::Commit,
+        tree: Oid,
+        parents: &[Oid],
+        author: &Signature,
+        committer: &Signature,
+        message: Option<&str>,
+    ) -> Result<Oid> {
+        let mut buffer = format!("tree {}\n", tree).into_bytes();
+        for parent in parents {
+            buffer.extend_from_slice(format!("parent {}\n", parent).as_bytes());
+        }
+        buffer.extend_from_slice(b"author ");
+        buffer.extend_from_slice(&format_raw_signature(author));
+        buffer.extend_from_slice(b"\ncommitter ");
+        buffer.extend_from_slice(&format_raw_signature(committer));
+        buffer.push(b'\n');
+        for header in extra_headers(commit.raw_header_bytes()) {
+            // A replaced message is written as UTF-8, so its old encoding no longer applies
+            if message.is_some() && header.starts_with(b"encoding ") {
+                continue;
+            }
+            buffer.extend_from_slice(header);
+            if !header.ends_with(b"\n") {
+                buffer.push(b'\n');
+            }
+        }
+        buffer.push(b'\n');
+        match message {
+            Some(message) => buffer.extend_from_slice(message.as_bytes()),
+            None => buffer.extend_from_slice(commit.message_raw_bytes()),
+        }
+
+        Ok(self.repo.odb()?.write(git2::ObjectType::Commit, &buffer)?)
+    }
+
+    /// Moves every ref in one transaction so either all of them move or none do, records
+    /// the run in the journal, and only then updates the working trees
+    fn update_refs(&self, updates: &[RefUpdate], run_id: &str) -> Result<journal::RunRecord> {
+        let head = self.repo.head()?;
+        let head_name = head.name().unwrap_or("HEAD").to_string();
+
+        for update in updates {
+            debug!(
+                "Updating {} from {} to {}",
+                update.name, update.old, update.new
+            );
+        }
+        let checkouts = worktree::other_checkouts(&self.repo)?;
+        let moves: Vec<_> = updates
+            .iter()
+            .map(|u| journal::RefMove {
+                name: u.name.clone(),
+                expected: Some(u.old),
+                target: u.new,
+            })
+            .collect();
+        journal::apply_ref_moves(&self.repo, &moves, &format!("rebrand: {}", run_id))
+            .context("Failed to update references; none were moved")?;
+
+        // Recorded before any checkout, so a failed checkout can still be undone. The refs
+        // have moved either way, so the checkouts follow even if recording failed.
+        let changes = updates
+            .iter()
+            .map(|u| journal::RefChange {
+                name: u.name.clone(),
+                old: u.old.to_string(),
+                new: u.new.to_string(),
+            })
+            .collect();
+        let run = journal::RunRecord::new(run_id.to_string(), changes);
+        let recorded =
+            journal::save(&self.repo, &run).context("Failed to record rewrite in the journal");
+
+        if !self.repo.is_bare() && updates.iter().any(|u| u.name == head_name) {
+            let new_head = self.repo.head()?.peel_to_commit()?;
+            self.repo
+                .reset(new_head.as_object(), git2::ResetType::Hard, None)?;
+        }
+        let moved: Vec<&str> = updates.iter().map(|u| u.name.as_str()).collect();
+        worktree::sync(&checkouts, &moved)?;
+        recorded?;
+        Ok(run)
+    }
+
+    fn match_author(&self, signature: &Signature) -> Option<RuleMatch<'_>> {
+        pattern::match_rules(
+            &self.author_rules,
+            signature.name().unwrap_or(""),
+            signature.email().unwrap_or(""),
+        )
+    }
+
+    fn match_committer(&self, signature: &Signature) -> Option<RuleMatch<'_>> {
+        let rules = self.committer_rules.as_ref().unwrap_or(&self.author_rules);
+        pattern::match_rules(
+            rules,
+            signature.name().unwrap_or(""),
+            signature.email().unwrap_or(""),
+        )
+    }
+
+    /// Rewrites trailer identities, then applies the configured message replacements
+    fn new_message(&self, commit: &git2::Commit) -> MessageRewrite {
+        let original = String::from_utf8_lossy(commit.message_raw_bytes()).into_owned();
+        let (message, trailer_hits) =
+            trailers::rewrite(&original, &self.config.trailer_keys, |name, email| {
+                let rule = pattern::match_rules(&self.author_rules, name, email)?;
+                let (new_name, new_email) = rule.mapping.target(name, email);
+                Some((format!("{} <{}>", new_name, new_email), rule.pattern))
+            })
+            .unwrap_or_else(|| (original.clone(), Vec::new()));
+        let message = self.message_replacer.apply(&message).unwrap_or(message);
+
+        MessageRewrite {
+            changed: message != original,
+            message,
+            trailer_hits,
+        }
+    }
+
+    /// Builds the replacement identity, keeping the original date unless re-dating was requested
+    fn new_signature(
+        &self,
+        mapping: &CompiledMapping,
+        original: &Signature,
+    ) -> Result<Signature<'static>> {
+        let (name, email) = mapping.target(
+            original.name().unwrap_or(""),
+            original.email().unwrap_or(""),
+        );
+        let signature = if self.config.redate {
+            Signature::now(name, email)?
+        } else {
+            Signature::new(name, email, &original.when())?
+        };
+        Ok(signature)
+    }
+
+    fn rewrite_history(&self, commits: &[CommitInfo]) -> Result<Vec<RefUpdate>> {
         info!("Starting history rewrite");
+        let refs = self.selected_refs()?;
         let mut revwalk = self.repo.revwalk()?;
-        revwalk.push_head()?;
+        for selected in &refs {
+            revwalk.push(selected.target)?;
+        }
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
 
-        let commit_ids: HashSet<String> = commits.iter().map(|c| c.id.clone()).collect();
-        let mut last_rewritten_id = None;
+        let affected: HashMap<Oid, &CommitInfo> = commits.iter().map(|c| (c.id, c)).collect();
+        // Maps every rewritten commit to its replacement so descendants can be re-parented
+        let mut rewritten: HashMap<Oid, Oid> = HashMap::new();
+        let mut walked = HashSet::new();
+        let mut tree_rewriter = self.tree_rewriter(&self.repo);
 
         for oid in revwalk {
             let oid = oid?;
+            walked.insert(oid);
             let commit = self.repo.find_commit(oid)?;
-            let commit_short_id = oid.to_string()[..8].to_string();
-
-            if commit_ids.contains(&commit_short_id) {
-                debug!("Rewriting commit: {}", commit_short_id);
-
-                let new_author =
-                    Signature::now(&self.config.new_author_name, &self.config.new_author_email)?;
-                let tree = commit.tree()?;
-                let parents: Vec<_> = commit
-                    .parent_ids()
-                    .map(|id| self.repo.find_commit(id))
-                    .collect::<Result<Vec<_>, _>>()?;
-                let parent_refs: Vec<_> = parents.iter().collect();
-
-                let new_id = self.repo.commit(
-                    None,
-                    &new_author,
-                    &new_author,
-                    commit.message().unwrap_or(""),
-                    &tree,
-                    &parent_refs,
-                )?;
-                last_rewritten_id = Some(new_id);
+
+            let parent_ids: Vec<Oid> = commit
+                .parent_ids()
+                .map(|id| rewritten.get(&id).copied().unwrap_or(id))
+                .collect();
+            let parents_changed = commit.parent_ids().ne(parent_ids.iter().copied());
+            let info = affected.get(&oid).copied();
+            let side = info.map(|info| info.matched_side);
+
+            if side.is_none() && !parents_changed {
+                continue;
             }
-        }
 
-        if let Some(new_id) = last_rewritten_id {
-            let obj = self.repo.find_object(new_id, None)?;
-            self.repo.reset(&obj, git2::ResetType::Hard, None)?;
+            let author_rule = side
+                .filter(|s| s.includes_author())
+                .and_then(|_| self.match_author(&commit.author()));
+            let author = match author_rule {
+                Some(rule) => self.new_signature(rule.mapping, &commit.author())?,
+                None => commit.author().to_owned(),
+            };
+            let committer_rule = side
+                .filter(|s| s.includes_committer())
+                .and_then(|_| self.match_committer(&commit.committer()));
+            let committer = match committer_rule {
+                Some(rule) => self.new_signature(rule.mapping, &commit.committer())?,
+                None => commit.committer().to_owned(),
+            };
+            match side {
+                Some(side) => debug!("Rewriting commit: {} ({})", oid, side),
+                None => debug!("Re-parenting commit: {}", oid),
+            }
+            let message = info
+                .and_then(|info| info.message_change.as_ref())
+                .map(|change| change.after.as_str());
+
+            let tree = match side {
+                Some(_) => tree_rewriter.rewrite(&commit.tree()?)?,
+                None => commit.tree_id(),
+            };
+            let new_id =
+                self.write_commit(&commit, tree, &parent_ids, &author, &committer, message)?;
+            rewritten.insert(oid, new_id);
         }
 
-        info!("History rewrite complete");
-        Ok(())
+        info!(
+            "History rewrite complete ({} commits recreated)",
+            rewritten.len()
+        );
+        self.ref_updates(&refs, &walked, &rewritten)
     }
 }
 
+// Abbreviated id that stays unambiguous within the repository
+fn short_id(commit: &git2::Commit) -> Result<String> {
+    let short = commit.as_object().short_id()?;
+    Ok(short.as_str().unwrap_or_default().to_string())
+}
+
+// Serializes a signature the way git stores it in commit and tag headers
+fn format_raw_signature(signature: &Signature) -> Vec<u8> {
+    let when = signature.when();
+    let offset = when.offset_minutes();
+    let mut raw = Vec::new();
+    raw.extend_from_slice(signature.name_bytes());
+    raw.extend_from_slice(b" <");
+    raw.extend_from_slice(signature.email_bytes());
+    raw.extend_from_slice(
+        format!(
+            "> {} {}{:02}{:02}",
+            when.seconds(),
+            if offset < 0 { '-' } else { '+' },
+            offset.abs() / 60,
+            offset.abs() % 60
+        )
+        .as_bytes(),
+    );
+    raw
+}
+
+// Commit headers other than tree, parents and identities, each with its continuation lines.
+// Signatures are dropped because they no longer match the rewritten commit.
+fn extra_headers(raw: &[u8]) -> Vec<&[u8]> {
+    let mut headers: Vec<&[u8]> = Vec::new();
+    let mut start = 0;
+    while start < raw.len() {
+        let mut end = start;
+        // A header runs until a newline that isn't followed by a continuation line
+        loop {
+            match raw[end..].iter().position(|&b| b == b'\n') {
+                Some(offset) => end += offset + 1,
+                None => end = raw.len(),
+            }
+            if end >= raw.len() || raw[end] != b' ' {
+                break;
+            }
+        }
+        headers.push(&raw[start..end]);
+        start = end;
+    }
+    headers.retain(|header| {
+        let key = header.split(|&b| b == b' ').next().unwrap_or_default();
+        !matches!(
+            key,
+            b"tree" | b"parent" | b"author" | b"committer" | b"gpgsig" | b"gpgsig-sha256"
+        )
+    });
+    headers
+}
+
+// Refs owned by git or by this tool that must never be rewritten. Notes and replace refs
+// point at commits of their own bookkeeping history, not at project history.
+fn is_internal_ref(name: &str) -> bool {
+    name == "refs/stash"
+        || name.starts_with("refs/notes/")
+        || name.starts_with("refs/replace/")
+        || name.starts_with(backup::BACKUP_NAMESPACE)
+}
+
+fn target_identity(mapping: &CompiledMapping, original: &Signature) -> String {
+    let (name, email) = mapping.target(
+        original.name().unwrap_or(""),
+        original.email().unwrap_or(""),
+    );
+    format!("{} <{}>", name, email)
+}
+
+fn format_identity(signature: &Signature) -> String {
+    format!(
+        "{} <{}>",
+        signature.name().unwrap_or(""),
+        signature.email().unwrap_or("")
+    )
+}
+
 // Logging setup
 pub fn setup_logger() -> Result<()> {
     let env = env_logger::Env::default()
@@ -0,0 +1,88 @@
+use crate::{GitRebrandError, IdentityMapping, Pattern};
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+/// Reads a `.mailmap` file into identity mappings
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<IdentityMapping>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read mailmap file: {}", path.display()))?;
+    Ok(parse(&content)?)
+}
+
+/// Parses the four `.mailmap` line forms:
+///
+/// ```text
+/// Proper Name <commit@email>
+/// <proper@email> <commit@email>
+/// Proper Name <proper@email> <commit@email>
+/// Proper Name <proper@email> Commit Name <commit@email>
+/// ```
+///
+/// Parts of the identity a line does not specify are kept as they are.
+pub fn parse(content: &str) -> Result<Vec<IdentityMapping>, GitRebrandError> {
+    let mut mappings = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || {
+            GitRebrandError::InvalidConfig(format!(
+                "Invalid mailmap entry on line {}: {}",
+                number + 1,
+                line
+            ))
+        };
+
+        let (proper_name, proper_email, rest) = split_email(line).ok_or_else(invalid)?;
+        let mapping = match split_email(rest) {
+            // Proper Name <commit@email>
+            None => {
+                if proper_name.is_empty() {
+                    return Err(invalid());
+                }
+                IdentityMapping {
+                    new_name: proper_name.to_string(),
+                    new_email: String::new(),
+                    patterns: vec![Pattern::Email {
+                        email: proper_email.to_string(),
+                    }],
+                }
+            },
+            Some((commit_name, commit_email, _)) => {
+                let pattern = if commit_name.is_empty() {
+                    Pattern::Email {
+                        email: commit_email.to_string(),
+                    }
+                } else {
+                    Pattern::Identity {
+                        identity: format!("{} <{}>", commit_name, commit_email),
+                    }
+                };
+                IdentityMapping {
+                    new_name: proper_name.to_string(),
+                    new_email: proper_email.to_string(),
+                    patterns: vec![pattern],
+                }
+            },
+        };
+        mappings.push(mapping);
+    }
+
+    Ok(mappings)
+}
+
+/// Formats one `Proper Name <proper@email> Commit Name <commit@email>` line
+pub(crate) fn format_entry(new_name: &str, new_email: &str, name: &str, email: &str) -> String {
+    format!("{} <{}> {} <{}>", new_name, new_email, name, email)
+}
+
+// Splits "Name <email> rest" into its trimmed name, the email and the remainder
+fn split_email(input: &str) -> Option<(&str, &str, &str)> {
+    let (name, rest) = input.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+    Some((name.trim(), email.trim(), rest))
+}
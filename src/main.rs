@@ -1,28 +1,40 @@
 use anyhow::{Context, Result};
-use clap::{Arg, Command};
-use git_rebrand::{setup_logger, Config, GitRebrander};
-use log::debug;
-use std::path::PathBuf;
+use clap::{Arg, ArgMatches, Command};
+use git_rebrand::{
+    backup, journal,
+    report::{self, OutputFormat, TableOptions},
+    setup_logger, Config, GitRebrandError, GitRebrander,
+};
+use log::{debug, info};
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
 
 // Version from Cargo.toml
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const ABOUT: &str = env!("CARGO_PKG_DESCRIPTION");
 
+fn path_arg() -> Arg {
+    Arg::new("path")
+        .help("Path to the Git repository")
+        .default_value(".")
+        .value_parser(clap::value_parser!(PathBuf))
+}
+
 fn build_cli() -> Command {
     Command::new("git-rebrand")
         .version(VERSION)
         .about(ABOUT)
-        .arg(
-            Arg::new("path")
-                .help("Path to the Git repository")
-                .default_value(".")
-                .value_parser(clap::value_parser!(PathBuf)),
-        )
+        .args_conflicts_with_subcommands(true)
+        .arg(path_arg())
         .arg(
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
                 .help("Enable verbose logging")
+                .global(true)
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(
@@ -31,19 +43,309 @@ fn build_cli() -> Command {
                 .help("Show what would be done, without making any changes")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Output format for the affected commits and summary")
+                .value_parser(["text", "json", "ndjson"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .value_name("N")
+                .help("Maximum number of commits listed in the dry-run table (0 for all)")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("50"),
+        )
+        .arg(
+            Arg::new("by-author")
+                .long("by-author")
+                .help("Summarize the dry run per original author instead of per commit")
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("no-backup")
                 .long("no-backup")
-                .help("Skip creating backup branch (USE WITH CAUTION)")
+                .help("Skip creating backup refs (USE WITH CAUTION)")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dirty-policy")
+                .long("dirty-policy")
+                .value_name("POLICY")
+                .help("How to handle uncommitted changes in the working tree")
+                .value_parser(["strict", "ignore-untracked", "auto-stash"]),
+        )
+        .arg(
+            Arg::new("backup-bundle")
+                .long("backup-bundle")
+                .value_name("PATH")
+                .help("Also write a git bundle of the original refs to a file or directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
         .arg(
             Arg::new("config")
                 .short('c')
                 .long("config")
                 .help("Path to configuration file")
+                .global(true)
                 .value_parser(clap::value_parser!(PathBuf)),
         )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .help("Rewrite every reference: branches, remote-tracking refs and tags")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("branches")
+                .long("branches")
+                .help("Rewrite all local branches")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("refs")
+                .long("refs")
+                .value_name("GLOB")
+                .help("Rewrite references matching a glob, e.g. 'refs/heads/release/*'")
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("range")
+                .long("range")
+                .value_name("FROM..TO")
+                .help("Only rewrite commits in a revision range, e.g. 'v1.0..HEAD'")
+                .global(true),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .value_name("DATE")
+                .help("Only rewrite commits made on or after a date (YYYY-MM-DD or RFC 3339)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .value_name("DATE")
+                .help("Only rewrite commits made on or before a date (YYYY-MM-DD or RFC 3339)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("after-commit")
+                .long("after-commit")
+                .value_name("COMMIT")
+                .help("Only rewrite commits that descend from a commit")
+                .global(true),
+        )
+        .arg(
+            Arg::new("scope")
+                .long("path")
+                .value_name("GLOB")
+                .help(
+                    "Only rebrand identities of commits touching paths matching a glob, e.g. 'vendor/oldco/**'",
+                )
+                .global(true)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("mailmap")
+                .long("mailmap")
+                .value_name("FILE")
+                .help("Load identity mappings from a .mailmap file")
+                .global(true)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .subcommand(
+            Command::new("export-mailmap")
+                .about("Write a .mailmap for every identity the configuration would rewrite")
+                .arg(path_arg())
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write to a file instead of standard output")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Restore every reference moved by a rewrite run (the latest by default)")
+                .arg(Arg::new("run-id").help("Run to undo, as listed by `history`"))
+                // The positional slot holds the run id, so the repository is an option
+                .arg(
+                    path_arg()
+                        .short('C')
+                        .long("repo")
+                        .value_name("PATH"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List past rewrite runs")
+                .arg(path_arg()),
+        )
+        .subcommand(
+            Command::new("restore-bundle")
+                .about("Restore the refs saved in a backup bundle")
+                .arg(
+                    Arg::new("bundle")
+                        .help("Bundle written with --backup-bundle")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(path_arg()),
+        )
+        .subcommand(
+            Command::new("prune-backups")
+                .about("Delete backup refs under refs/rebrand/backup/ left by older runs")
+                .arg(
+                    Arg::new("older-than")
+                        .long("older-than")
+                        .value_name("AGE")
+                        .help("Minimum age of the backups to delete, e.g. '30d', '12h' or '2w'")
+                        .required(true),
+                )
+                .arg(path_arg()),
+        )
+}
+
+fn repo_path(matches: &ArgMatches) -> PathBuf {
+    matches
+        .get_one::<PathBuf>("path")
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Builds the configuration from a file, a mailmap, or interactive prompts
+fn load_config(matches: &ArgMatches) -> Result<Config> {
+    let mut config = if let Some(config_path) = matches.get_one::<PathBuf>("config") {
+        Config::from_file(config_path).context("Failed to load configuration file")?
+    } else if matches.contains_id("mailmap") {
+        Config::default()
+    } else {
+        Config::prompt_interactive().context("Failed to get configuration from user")?
+    };
+
+    // Set repository path in config
+    config.repo_path = repo_path(matches);
+    debug!("Using repository path: {}", config.repo_path.display());
+
+    if let Some(mailmap) = matches.get_one::<PathBuf>("mailmap") {
+        config.mailmap = Some(mailmap.clone());
+    }
+
+    // Reference selection on the command line extends the configured globs
+    if matches.get_flag("all") {
+        config
+            .refs
+            .extend(["refs/heads/*", "refs/remotes/*", "refs/tags/*"].map(String::from));
+    }
+    if matches.get_flag("branches") {
+        config.refs.push("refs/heads/*".to_string());
+    }
+    if let Some(globs) = matches.get_many::<String>("refs") {
+        config.refs.extend(globs.cloned());
+    }
+
+    if let Some(globs) = matches.get_many::<String>("scope") {
+        config.paths.extend(globs.cloned());
+    }
+
+    // Commit window options override the configuration file
+    for (arg, field) in [
+        ("range", &mut config.range),
+        ("since", &mut config.since),
+        ("until", &mut config.until),
+        ("after-commit", &mut config.after_commit),
+    ] {
+        if let Some(value) = matches.get_one::<String>(arg) {
+            *field = Some(value.clone());
+        }
+    }
+
+    Ok(config)
+}
+
+fn export_mailmap(matches: &ArgMatches) -> Result<()> {
+    let config = load_config(matches)?;
+    // Exporting only reads the configuration, so a dirty working tree is fine
+    let rebrander = GitRebrander::open(config).context("Failed to initialize git-rebrand")?;
+    let mailmap = rebrander
+        .export_mailmap()
+        .context("Failed to export mailmap")?;
+    match matches.get_one::<PathBuf>("output") {
+        Some(output) => {
+            fs::write(output, mailmap).context("Failed to write mailmap")?;
+            info!("Wrote mailmap to {}", output.display());
+        },
+        None => print!("{}", mailmap),
+    }
+    Ok(())
+}
+
+fn undo(matches: &ArgMatches) -> Result<()> {
+    let run_id = matches.get_one::<String>("run-id").map(String::as_str);
+    let run = journal::undo(repo_path(matches), run_id).context("Undo failed")?;
+    println!(
+        "Undid run {} ({} references restored)",
+        run.id,
+        run.refs.len()
+    );
+    Ok(())
+}
+
+fn history(matches: &ArgMatches) -> Result<()> {
+    let runs = journal::history(repo_path(matches)).context("Failed to read rewrite history")?;
+    if runs.is_empty() {
+        println!("No rewrite runs recorded");
+    }
+    for run in runs {
+        let status = match &run.undone_at {
+            Some(at) => format!("undone {}", at),
+            None => "active".to_string(),
+        };
+        println!(
+            "{}  {}  {} refs  {}",
+            run.id,
+            run.timestamp,
+            run.refs.len(),
+            status
+        );
+    }
+    Ok(())
+}
+
+fn restore_bundle(matches: &ArgMatches) -> Result<()> {
+    let bundle = matches
+        .get_one::<PathBuf>("bundle")
+        .cloned()
+        .unwrap_or_default();
+    let refs =
+        backup::restore_bundle(repo_path(matches), &bundle).context("Failed to restore bundle")?;
+    for name in &refs {
+        println!("Restored {}", name);
+    }
+    Ok(())
+}
+
+fn prune_backups(matches: &ArgMatches) -> Result<()> {
+    let older_than = matches
+        .get_one::<String>("older-than")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let pruned =
+        backup::prune(repo_path(matches), older_than).context("Failed to prune backups")?;
+    for run_id in &pruned {
+        println!("Deleted backups of run {}", run_id);
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -53,48 +355,83 @@ fn main() -> Result<()> {
     // Parse command line arguments
     let matches = build_cli().get_matches();
 
-    // Get repository path
-    let repo_path = matches
-        .get_one::<PathBuf>("path")
-        .cloned()
-        .unwrap_or_else(|| PathBuf::from("."));
-
-    debug!("Using repository path: {}", repo_path.display());
-
     // Handle verbose flag
     if matches.get_flag("verbose") {
         debug!("Verbose logging enabled");
     }
 
-    // Load config either from file or through interactive prompts
-    let mut config = if let Some(config_path) = matches.get_one::<PathBuf>("config") {
-        Config::from_file(config_path).context("Failed to load configuration file")?
-    } else {
-        Config::prompt_interactive().context("Failed to get configuration from user")?
-    };
+    match matches.subcommand() {
+        Some(("export-mailmap", sub_matches)) => return export_mailmap(sub_matches),
+        Some(("undo", sub_matches)) => return undo(sub_matches),
+        Some(("history", sub_matches)) => return history(sub_matches),
+        Some(("restore-bundle", sub_matches)) => return restore_bundle(sub_matches),
+        Some(("prune-backups", sub_matches)) => return prune_backups(sub_matches),
+        _ => {},
+    }
 
-    // Set repository path in config
-    config.repo_path = repo_path;
+    let mut config = load_config(&matches)?;
     config.create_backup = !matches.get_flag("no-backup");
+    if let Some(bundle) = matches.get_one::<PathBuf>("backup-bundle") {
+        config.backup_bundle = Some(bundle.clone());
+    }
+    if let Some(policy) = matches.get_one::<String>("dirty-policy") {
+        config.dirty_policy = policy.parse()?;
+    }
+
+    let format: OutputFormat = matches
+        .get_one::<String>("format")
+        .map(|format| format.parse())
+        .transpose()?
+        .unwrap_or_default();
 
     // Create GitRebrander instance
     let rebrander = GitRebrander::new(config).context("Failed to initialize git-rebrand")?;
 
     // Execute based on dry-run flag
-    if matches.get_flag("dry-run") {
+    let result = if matches.get_flag("dry-run") {
         debug!("Performing dry run");
-        rebrander.dry_run().context("Dry run failed")?;
+        rebrander
+            .preview()
+            .map(|preview| (preview.commits, preview.tags, None))
+            .context("Dry run failed")
     } else {
         debug!("Performing actual rewrite");
-        rebrander.run().context("Rewrite operation failed")?;
-    }
+        rebrander
+            .run()
+            .map(|report| (report.commits, report.tags, Some(report.run)))
+            .context("Rewrite operation failed")
+    };
 
-    Ok(())
+    match result {
+        // Dry runs in text format are previewed as a table
+        Ok((commits, tags, None)) if format == OutputFormat::Text => {
+            let options = TableOptions {
+                limit: matches.get_one::<usize>("limit").copied().unwrap_or(50),
+                group_by_author: matches.get_flag("by-author"),
+                color: io::stdout().is_terminal(),
+            };
+            report::write_table(&mut io::stdout(), &commits, &tags, &options)
+        },
+        Ok((commits, tags, run)) => {
+            report::write(&mut io::stdout(), format, &commits, &tags, run.as_ref())
+        },
+        Err(e) => {
+            // Pipelines still get an empty report when nothing matched
+            if matches!(
+                e.downcast_ref::<GitRebrandError>(),
+                Some(GitRebrandError::NoMatchingCommits)
+            ) {
+                report::write(&mut io::stdout(), format, &[], &[], None)?;
+            }
+            Err(e)
+        },
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use git_rebrand::DirtyPolicy;
 
     #[test]
     fn verify_cli() {
@@ -116,4 +453,217 @@ mod tests {
             "/path/to/repo"
         );
     }
+
+    #[test]
+    fn test_cli_ref_selection() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "--branches",
+            "--refs",
+            "refs/tags/v*",
+            "--refs",
+            "refs/remotes/origin/*",
+        ]);
+
+        assert!(matches.get_flag("branches"));
+        assert!(!matches.get_flag("all"));
+        let globs: Vec<_> = matches.get_many::<String>("refs").unwrap().collect();
+        assert_eq!(globs, vec!["refs/tags/v*", "refs/remotes/origin/*"]);
+    }
+
+    #[test]
+    fn test_cli_commit_window() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "--range",
+            "v1.0..HEAD",
+            "--since",
+            "2024-01-01",
+            "--after-commit",
+            "abc123",
+            "--path",
+            "vendor/oldco/**",
+        ]);
+
+        assert_eq!(
+            matches.get_one::<String>("range").map(String::as_str),
+            Some("v1.0..HEAD")
+        );
+        assert_eq!(
+            matches.get_one::<String>("since").map(String::as_str),
+            Some("2024-01-01")
+        );
+        assert!(matches.get_one::<String>("until").is_none());
+        assert_eq!(
+            matches
+                .get_one::<String>("after-commit")
+                .map(String::as_str),
+            Some("abc123")
+        );
+        let scope: Vec<_> = matches.get_many::<String>("scope").unwrap().collect();
+        assert_eq!(scope, vec!["vendor/oldco/**"]);
+    }
+
+    #[test]
+    fn test_cli_undo() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "undo",
+            "20240321-123456",
+            "-C",
+            "/path/to/repo",
+        ]);
+
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "undo");
+        assert_eq!(
+            sub_matches.get_one::<String>("run-id").map(String::as_str),
+            Some("20240321-123456")
+        );
+        assert_eq!(repo_path(sub_matches), PathBuf::from("/path/to/repo"));
+
+        // Without a run id the latest run of the given repository is undone
+        let matches =
+            build_cli().get_matches_from(vec!["git-rebrand", "undo", "--repo", "/path/to/repo"]);
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(sub_matches.get_one::<String>("run-id"), None);
+        assert_eq!(repo_path(sub_matches), PathBuf::from("/path/to/repo"));
+
+        let matches = build_cli().get_matches_from(vec!["git-rebrand", "undo"]);
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(repo_path(sub_matches), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_cli_dirty_policy() {
+        let matches =
+            build_cli().get_matches_from(vec!["git-rebrand", "--dirty-policy", "auto-stash"]);
+        let policy: DirtyPolicy = matches
+            .get_one::<String>("dirty-policy")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(policy, DirtyPolicy::AutoStash);
+
+        assert!(build_cli()
+            .try_get_matches_from(vec!["git-rebrand", "--dirty-policy", "yolo"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_format() {
+        let matches = build_cli().get_matches_from(vec!["git-rebrand", "--dry-run"]);
+        assert_eq!(
+            matches.get_one::<String>("format").map(String::as_str),
+            Some("text")
+        );
+
+        let matches =
+            build_cli().get_matches_from(vec!["git-rebrand", "--dry-run", "--format", "ndjson"]);
+        let format: OutputFormat = matches
+            .get_one::<String>("format")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(format, OutputFormat::Ndjson);
+
+        assert!(build_cli()
+            .try_get_matches_from(vec!["git-rebrand", "--format", "xml"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_table_options() {
+        let matches = build_cli().get_matches_from(vec!["git-rebrand", "--dry-run"]);
+        assert_eq!(matches.get_one::<usize>("limit"), Some(&50));
+        assert!(!matches.get_flag("by-author"));
+
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "--dry-run",
+            "--limit",
+            "0",
+            "--by-author",
+        ]);
+        assert_eq!(matches.get_one::<usize>("limit"), Some(&0));
+        assert!(matches.get_flag("by-author"));
+
+        assert!(build_cli()
+            .try_get_matches_from(vec!["git-rebrand", "--limit", "many"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_backup_bundle() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "--backup-bundle",
+            "/backups",
+            "/path/to/repo",
+        ]);
+        assert_eq!(
+            matches.get_one::<PathBuf>("backup-bundle").unwrap(),
+            &PathBuf::from("/backups")
+        );
+
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "restore-bundle",
+            "/backups/rebrand.bundle",
+        ]);
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "restore-bundle");
+        assert_eq!(
+            sub_matches.get_one::<PathBuf>("bundle").unwrap(),
+            &PathBuf::from("/backups/rebrand.bundle")
+        );
+        assert_eq!(repo_path(sub_matches), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_cli_prune_backups() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "prune-backups",
+            "--older-than",
+            "30d",
+        ]);
+
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "prune-backups");
+        assert_eq!(
+            sub_matches
+                .get_one::<String>("older-than")
+                .map(String::as_str),
+            Some("30d")
+        );
+        assert!(build_cli()
+            .try_get_matches_from(vec!["git-rebrand", "prune-backups"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cli_export_mailmap() {
+        let matches = build_cli().get_matches_from(vec![
+            "git-rebrand",
+            "export-mailmap",
+            "--mailmap",
+            "old.mailmap",
+            "-o",
+            ".mailmap",
+            "/path/to/repo",
+        ]);
+
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "export-mailmap");
+        assert_eq!(
+            sub_matches.get_one::<PathBuf>("output").unwrap(),
+            &PathBuf::from(".mailmap")
+        );
+        assert_eq!(
+            sub_matches.get_one::<PathBuf>("path").unwrap(),
+            &PathBuf::from("/path/to/repo")
+        );
+        assert!(sub_matches.contains_id("mailmap"));
+    }
 }
@@ -0,0 +1,224 @@
+use crate::GitRebrandError;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// A single author pattern as written in the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Pattern {
+    /// Case-insensitive substring of the email when it contains '@', of the name otherwise
+    Plain(String),
+    /// Regular expression tested against the email and the name
+    Regex { regex: String },
+    /// Exact email address, ignoring case
+    Email { email: String },
+    /// Exact email domain (`oldcompany.com` or `@oldcompany.com`), ignoring case
+    Domain { domain: String },
+    /// Exact author name, ignoring case
+    Name { name: String },
+    /// Substring of the author name, ignoring case
+    NameContains { name_contains: String },
+    /// Shell-style glob tested against the email and the name, ignoring case
+    Glob { glob: String },
+    /// Exact `Name <email>` pair, ignoring case
+    Identity { identity: String },
+}
+
+impl From<&str> for Pattern {
+    fn from(pattern: &str) -> Self {
+        Self::Plain(pattern.to_string())
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(pattern: String) -> Self {
+        Self::Plain(pattern)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain(pattern) => write!(f, "{}", pattern),
+            Self::Regex { regex } => write!(f, "regex:{}", regex),
+            Self::Email { email } => write!(f, "email:{}", email),
+            Self::Domain { domain } => write!(f, "domain:{}", domain),
+            Self::Name { name } => write!(f, "name:{}", name),
+            Self::NameContains { name_contains } => write!(f, "name_contains:{}", name_contains),
+            Self::Glob { glob } => write!(f, "glob:{}", glob),
+            Self::Identity { identity } => write!(f, "identity:{}", identity),
+        }
+    }
+}
+
+// Pattern ready for matching, compiled once when the rebrander is created
+#[derive(Debug, Clone)]
+struct CompiledPattern {
+    source: Pattern,
+    matcher: Matcher,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    EmailContains(String),
+    NameContains(String),
+    Regex(Regex),
+    Email(String),
+    Domain(String),
+    Name(String),
+    Glob(GlobMatcher),
+    Identity(String, String),
+}
+
+impl CompiledPattern {
+    fn new(pattern: &Pattern) -> Result<Self, GitRebrandError> {
+        let matcher = match pattern {
+            Pattern::Plain(plain) => {
+                let lower = plain.to_lowercase();
+                if lower.contains('@') {
+                    Matcher::EmailContains(lower)
+                } else {
+                    Matcher::NameContains(lower)
+                }
+            },
+            Pattern::Regex { regex } => Matcher::Regex(
+                Regex::new(regex)
+                    .map_err(|e| GitRebrandError::InvalidPattern(format!("{}: {}", pattern, e)))?,
+            ),
+            Pattern::Email { email } => Matcher::Email(email.to_lowercase()),
+            Pattern::Domain { domain } => {
+                Matcher::Domain(domain.trim_start_matches('@').to_lowercase())
+            },
+            Pattern::Name { name } => Matcher::Name(name.to_lowercase()),
+            Pattern::NameContains { name_contains } => {
+                Matcher::NameContains(name_contains.to_lowercase())
+            },
+            Pattern::Glob { glob } => Matcher::Glob(
+                GlobBuilder::new(glob)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| GitRebrandError::InvalidPattern(format!("{}: {}", pattern, e)))?
+                    .compile_matcher(),
+            ),
+            Pattern::Identity { identity } => {
+                let (name, email) = parse_identity(identity).ok_or_else(|| {
+                    GitRebrandError::InvalidPattern(format!("{}: expected 'Name <email>'", pattern))
+                })?;
+                Matcher::Identity(name.to_lowercase(), email.to_lowercase())
+            },
+        };
+        Ok(Self {
+            source: pattern.clone(),
+            matcher,
+        })
+    }
+
+    fn compile_all(patterns: &[Pattern]) -> Result<Vec<Self>, GitRebrandError> {
+        patterns.iter().map(Self::new).collect()
+    }
+
+    fn matches(&self, name: &str, email: &str) -> bool {
+        match &self.matcher {
+            Matcher::EmailContains(pattern) => email.to_lowercase().contains(pattern),
+            Matcher::NameContains(pattern) => name.to_lowercase().contains(pattern),
+            Matcher::Regex(regex) => regex.is_match(email) || regex.is_match(name),
+            Matcher::Email(pattern) => email.to_lowercase() == *pattern,
+            Matcher::Domain(pattern) => email
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| domain.to_lowercase() == *pattern),
+            Matcher::Name(pattern) => name.to_lowercase() == *pattern,
+            Matcher::Glob(glob) => glob.is_match(email) || glob.is_match(name),
+            Matcher::Identity(pattern_name, pattern_email) => {
+                name.to_lowercase() == *pattern_name && email.to_lowercase() == *pattern_email
+            },
+        }
+    }
+}
+
+// Splits `Name <email>` into its trimmed parts
+pub(crate) fn parse_identity(identity: &str) -> Option<(&str, &str)> {
+    let (name, rest) = identity.split_once('<')?;
+    let email = rest.trim_end().strip_suffix('>')?;
+    Some((name.trim(), email.trim()))
+}
+
+// Identity mapping with its patterns compiled
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledMapping {
+    pub(crate) new_name: String,
+    pub(crate) new_email: String,
+    /// Names the mapping in reports: its target, `*` for kept parts, and its patterns
+    pub(crate) label: String,
+    patterns: Vec<CompiledPattern>,
+}
+
+impl CompiledMapping {
+    pub(crate) fn new(
+        new_name: &str,
+        new_email: &str,
+        patterns: &[Pattern],
+    ) -> Result<Self, GitRebrandError> {
+        let patterns = CompiledPattern::compile_all(patterns)?;
+        let or_kept = |value: &str| {
+            if value.is_empty() {
+                "*".to_string()
+            } else {
+                value.to_string()
+            }
+        };
+        let sources: Vec<_> = patterns.iter().map(|p| p.source.to_string()).collect();
+        Ok(Self {
+            new_name: new_name.to_string(),
+            new_email: new_email.to_string(),
+            label: format!(
+                "{} <{}> (for {})",
+                or_kept(new_name),
+                or_kept(new_email),
+                sources.join(", ")
+            ),
+            patterns,
+        })
+    }
+
+    /// Replacement name and email for an identity; empty targets keep the original value
+    pub(crate) fn target<'a>(&'a self, name: &'a str, email: &'a str) -> (&'a str, &'a str) {
+        let new_name = if self.new_name.is_empty() {
+            name
+        } else {
+            &self.new_name
+        };
+        let new_email = if self.new_email.is_empty() {
+            email
+        } else {
+            &self.new_email
+        };
+        (new_name, new_email)
+    }
+}
+
+// The mapping selected for an identity and the pattern that selected it
+#[derive(Debug)]
+pub(crate) struct RuleMatch<'a> {
+    pub(crate) mapping: &'a CompiledMapping,
+    pub(crate) pattern: String,
+}
+
+// Returns the first mapping with a pattern matching the identity
+pub(crate) fn match_rules<'a>(
+    rules: &'a [CompiledMapping],
+    name: &str,
+    email: &str,
+) -> Option<RuleMatch<'a>> {
+    rules.iter().find_map(|mapping| {
+        mapping
+            .patterns
+            .iter()
+            .find(|p| p.matches(name, email))
+            .map(|p| RuleMatch {
+                mapping,
+                pattern: p.source.to_string(),
+            })
+    })
+}
@@ -0,0 +1,64 @@
+use crate::GitRebrandError;
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// A text substitution as written in the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextReplacement {
+    /// Text to find; a regular expression when `regex` is set
+    pub find: String,
+    /// Replacement text; `$1` or `${name}` expand capture groups of regex rules
+    pub replace: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+// Replacement rules compiled once and applied in order
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Replacer {
+    rules: Vec<(Regex, TextReplacement)>,
+}
+
+impl Replacer {
+    pub(crate) fn new(replacements: &[TextReplacement]) -> Result<Self, GitRebrandError> {
+        let rules = replacements
+            .iter()
+            .map(|replacement| {
+                let source = if replacement.regex {
+                    Cow::Borrowed(replacement.find.as_str())
+                } else {
+                    Cow::Owned(regex::escape(&replacement.find))
+                };
+                let regex = Regex::new(&source).map_err(|e| {
+                    GitRebrandError::InvalidPattern(format!("{}: {}", replacement.find, e))
+                })?;
+                Ok((regex, replacement.clone()))
+            })
+            .collect::<Result<_, GitRebrandError>>()?;
+        Ok(Self { rules })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies every rule in order, returning `None` when the text is unchanged
+    pub(crate) fn apply(&self, text: &str) -> Option<String> {
+        let mut current = Cow::Borrowed(text);
+        for (regex, replacement) in &self.rules {
+            let replaced = if replacement.regex {
+                regex.replace_all(&current, replacement.replace.as_str())
+            } else {
+                regex.replace_all(&current, NoExpand(&replacement.replace))
+            };
+            if let Cow::Owned(replaced) = replaced {
+                current = Cow::Owned(replaced);
+            }
+        }
+        match current {
+            Cow::Owned(changed) if changed != text => Some(changed),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use crate::{
+    journal::{RefChange, RunRecord},
+    CommitInfo, GitRebrandError, TagInfo,
+};
+use anyhow::Result;
+use colored::{ColoredString, Colorize};
+use prettytable::{format, Cell, Row, Table};
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap, io::Write, str::FromStr};
+
+// Cell widths beyond which text is cut off with an ellipsis
+const IDENTITY_WIDTH: usize = 40;
+const PATTERN_WIDTH: usize = 24;
+const SUBJECT_WIDTH: usize = 50;
+
+/// How dry-run and rewrite results are written to standard output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Log lines only
+    #[default]
+    Text,
+    /// A single JSON document holding every commit and the summary
+    Json,
+    /// One JSON object per line: a `commit` per affected commit, then the `summary`
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = GitRebrandError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(GitRebrandError::InvalidConfig(format!(
+                "Unknown output format '{}': expected text, json or ndjson",
+                format
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct IdentityChange<'a> {
+    old: &'a str,
+    new: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct TrailerRecord<'a> {
+    key: &'a str,
+    old: &'a str,
+    new: &'a str,
+    matched_pattern: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageRecord<'a> {
+    before: &'a str,
+    after: &'a str,
+}
+
+// An affected commit as written to the report
+#[derive(Debug, Serialize)]
+struct CommitRecord<'a> {
+    id: String,
+    short_id: &'a str,
+    timestamp: String,
+    subject: &'a str,
+    author: IdentityChange<'a>,
+    committer: IdentityChange<'a>,
+    matched_pattern: Option<&'a str>,
+    /// Where the commit matched: `author`, `committer`, `trailer`, `message` and/or `tree`
+    match_location: Vec<&'static str>,
+    trailers: Vec<TrailerRecord<'a>>,
+    message: Option<MessageRecord<'a>>,
+    matched_paths: &'a [String],
+}
+
+impl<'a> From<&'a CommitInfo> for CommitRecord<'a> {
+    fn from(commit: &'a CommitInfo) -> Self {
+        let mut match_location = Vec::new();
+        if commit.matched_side.includes_author() {
+            match_location.push("author");
+        }
+        if commit.matched_side.includes_committer() {
+            match_location.push("committer");
+        }
+        if !commit.trailer_hits.is_empty() {
+            match_location.push("trailer");
+        }
+        if commit.message_change.is_some() {
+            match_location.push("message");
+        }
+        if commit.tree_changed {
+            match_location.push("tree");
+        }
+
+        Self {
+            id: commit.id.to_string(),
+            short_id: &commit.short_id,
+            timestamp: commit.iso_timestamp(),
+            subject: &commit.subject,
+            author: IdentityChange {
+                old: &commit.author,
+                new: &commit.new_author,
+            },
+            committer: IdentityChange {
+                old: &commit.committer,
+                new: &commit.new_committer,
+            },
+            matched_pattern: Some(commit.matched_pattern.as_str()).filter(|p| !p.is_empty()),
+            match_location,
+            trailers: commit
+                .trailer_hits
+                .iter()
+                .map(|hit| TrailerRecord {
+                    key: &hit.key,
+                    old: &hit.old,
+                    new: &hit.new,
+                    matched_pattern: &hit.matched_pattern,
+                })
+                .collect(),
+            message: commit.message_change.as_ref().map(|change| MessageRecord {
+                before: &change.before,
+                after: &change.after,
+            }),
+            matched_paths: &commit.matched_paths,
+        }
+    }
+}
+
+// An annotated tag whose tagger is rebranded
+#[derive(Debug, Serialize)]
+struct TagRecord<'a> {
+    name: &'a str,
+    tagger: IdentityChange<'a>,
+    matched_pattern: &'a str,
+}
+
+impl<'a> From<&'a TagInfo> for TagRecord<'a> {
+    fn from(tag: &'a TagInfo) -> Self {
+        Self {
+            name: &tag.name,
+            tagger: IdentityChange {
+                old: &tag.tagger,
+                new: &tag.new_tagger,
+            },
+            matched_pattern: &tag.matched_pattern,
+        }
+    }
+}
+
+/// Totals over the affected commits and tags, and the refs moved when the rewrite ran
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// `dry-run`, or `run` once references were moved
+    pub mode: String,
+    pub commits: usize,
+    pub author_matches: usize,
+    pub committer_matches: usize,
+    pub trailer_identities: usize,
+    pub message_changes: usize,
+    pub tree_changes: usize,
+    /// Annotated tags whose tagger is rebranded
+    pub tagger_matches: usize,
+    /// Number of commits rewritten by each mapping, keyed by its label
+    pub mappings: BTreeMap<String, usize>,
+    pub run_id: Option<String>,
+    pub refs: Vec<RefChange>,
+}
+
+impl Summary {
+    pub fn new(commits: &[CommitInfo], tags: &[TagInfo], run: Option<&RunRecord>) -> Self {
+        let mut mappings: BTreeMap<String, usize> = BTreeMap::new();
+        for label in commits.iter().flat_map(|c| &c.mappings) {
+            *mappings.entry(label.clone()).or_default() += 1;
+        }
+
+        Self {
+            mode: if run.is_some() { "run" } else { "dry-run" }.to_string(),
+            commits: commits.len(),
+            author_matches: commits
+                .iter()
+                .filter(|c| c.matched_side.includes_author())
+                .count(),
+            committer_matches: commits
+                .iter()
+                .filter(|c| c.matched_side.includes_committer())
+                .count(),
+            trailer_identities: commits.iter().map(|c| c.trailer_hits.len()).sum(),
+            message_changes: commits
+                .iter()
+                .filter(|c| c.message_change.is_some())
+                .count(),
+            tree_changes: commits.iter().filter(|c| c.tree_changed).count(),
+            tagger_matches: tags.len(),
+            mappings,
+            run_id: run.map(|run| run.id.clone()),
+            refs: run.map(|run| run.refs.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+// Lines of an NDJSON report, told apart by their `type`
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line<'a> {
+    Commit(&'a CommitRecord<'a>),
+    Tag(&'a TagRecord<'a>),
+    Summary(&'a Summary),
+}
+
+/// Writes the affected commits and tags and their summary in `format`; `Text` writes
+/// nothing.
+///
+/// `run` is the journal record of the rewrite, or `None` for a dry run.
+pub fn write<W: Write>(
+    out: &mut W,
+    format: OutputFormat,
+    commits: &[CommitInfo],
+    tags: &[TagInfo],
+    run: Option<&RunRecord>,
+) -> Result<()> {
+    let summary = Summary::new(commits, tags, run);
+    match format {
+        OutputFormat::Text => {},
+        OutputFormat::Json => {
+            #[derive(Serialize)]
+            struct Report<'a> {
+                commits: Vec<CommitRecord<'a>>,
+                tags: Vec<TagRecord<'a>>,
+                summary: &'a Summary,
+            }
+            let report = Report {
+                commits: commits.iter().map(CommitRecord::from).collect(),
+                tags: tags.iter().map(TagRecord::from).collect(),
+                summary: &summary,
+            };
+            serde_json::to_writer_pretty(&mut *out, &report)?;
+            writeln!(out)?;
+        },
+        OutputFormat::Ndjson => {
+            for commit in commits {
+                let record = CommitRecord::from(commit);
+                serde_json::to_writer(&mut *out, &Line::Commit(&record))?;
+                writeln!(out)?;
+            }
+            for tag in tags {
+                let record = TagRecord::from(tag);
+                serde_json::to_writer(&mut *out, &Line::Tag(&record))?;
+                writeln!(out)?;
+            }
+            serde_json::to_writer(&mut *out, &Line::Summary(&summary))?;
+            writeln!(out)?;
+        },
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// How the dry-run preview table is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableOptions {
+    /// Maximum number of commits listed; `0` lists every commit
+    pub limit: usize,
+    /// Show one row per original author instead of one per commit
+    pub group_by_author: bool,
+    /// Color-code identities and patterns with ANSI escapes
+    pub color: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            limit: 50,
+            group_by_author: false,
+            color: false,
+        }
+    }
+}
+
+/// Renders the affected commits as a table: short id, date, old → new identity, matched
+/// pattern and subject, followed by the rebranded taggers and a one-line summary
+pub fn write_table<W: Write>(
+    out: &mut W,
+    commits: &[CommitInfo],
+    tags: &[TagInfo],
+    options: &TableOptions,
+) -> Result<()> {
+    let paint = |text: String, style: fn(&str) -> ColoredString| {
+        if options.color {
+            style(&text).to_string()
+        } else {
+            text
+        }
+    };
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    let shown = if options.group_by_author {
+        table.set_titles(Row::new(
+            ["Author", "New identity", "Commits", "First", "Last"]
+                .into_iter()
+                .map(|title| Cell::new(&paint(title.to_string(), |s| s.bold())))
+                .collect(),
+        ));
+        let groups = group_by_author(commits);
+        for group in &groups {
+            let unchanged = group.author == group.new_author;
+            let new_author = truncate(group.new_author, IDENTITY_WIDTH);
+            table.add_row(Row::new(vec![
+                Cell::new(&paint(truncate(group.author, IDENTITY_WIDTH), |s| s.red())),
+                Cell::new(&if unchanged {
+                    paint(new_author, |s| s.dimmed())
+                } else {
+                    paint(new_author, |s| s.green())
+                }),
+                Cell::new(&group.commits.to_string()).style_spec("r"),
+                Cell::new(&group.first),
+                Cell::new(&group.last),
+            ]));
+        }
+        commits.len()
+    } else {
+        table.set_titles(Row::new(
+            ["Commit", "Date", "Identity", "Pattern", "Subject"]
+                .into_iter()
+                .map(|title| Cell::new(&paint(title.to_string(), |s| s.bold())))
+                .collect(),
+        ));
+        let limit = if options.limit == 0 {
+            commits.len()
+        } else {
+            options.limit
+        };
+        for commit in commits.iter().take(limit) {
+            let mut identities = Vec::new();
+            let side = commit.matched_side;
+            if side.includes_author() {
+                identities.push(identity_change(&commit.author, &commit.new_author, &paint));
+            }
+            if side.includes_committer()
+                && (&commit.committer, &commit.new_committer)
+                    != (&commit.author, &commit.new_author)
+            {
+                identities.push(identity_change(
+                    &commit.committer,
+                    &commit.new_committer,
+                    &paint,
+                ));
+            }
+            if identities.is_empty() {
+                identities.push(paint(truncate(&commit.author, IDENTITY_WIDTH), |s| {
+                    s.dimmed()
+                }));
+            }
+            let pattern = match commit.matched_pattern.as_str() {
+                "" => "-".to_string(),
+                pattern => paint(truncate(pattern, PATTERN_WIDTH), |s| s.yellow()),
+            };
+
+            table.add_row(Row::new(vec![
+                Cell::new(&paint(commit.short_id.clone(), |s| s.cyan())),
+                Cell::new(&iso_date(commit)),
+                Cell::new(&identities.join("\n")),
+                Cell::new(&pattern),
+                Cell::new(&truncate(&commit.subject, SUBJECT_WIDTH)),
+            ]));
+        }
+        limit.min(commits.len())
+    };
+    table.print(out)?;
+
+    if shown < commits.len() {
+        let more = format!(
+            "... {} more commits not shown; use --limit 0 to list them all",
+            commits.len() - shown
+        );
+        writeln!(out, "{}", paint(more, |s| s.dimmed()))?;
+    }
+    for tag in tags {
+        let name = tag.name.strip_prefix("refs/tags/").unwrap_or(&tag.name);
+        writeln!(
+            out,
+            "tag {}: {}",
+            paint(name.to_string(), |s| s.cyan()),
+            identity_change(&tag.tagger, &tag.new_tagger, &paint)
+        )?;
+    }
+    let summary = Summary::new(commits, tags, None);
+    let total = format!(
+        "{} commits would be rewritten ({} authors, {} committers, {} trailer identities, {} with file changes, {} taggers)",
+        summary.commits,
+        summary.author_matches,
+        summary.committer_matches,
+        summary.trailer_identities,
+        summary.tree_changes,
+        summary.tagger_matches
+    );
+    writeln!(out, "{}", paint(total, |s| s.bold()))?;
+    out.flush()?;
+    Ok(())
+}
+
+// Affected commits sharing an original author and its replacement
+struct AuthorGroup<'a> {
+    author: &'a str,
+    new_author: &'a str,
+    commits: usize,
+    first: String,
+    last: String,
+}
+
+// Groups commits by original author, largest groups first
+fn group_by_author(commits: &[CommitInfo]) -> Vec<AuthorGroup<'_>> {
+    let mut groups: BTreeMap<(&str, &str), AuthorGroup> = BTreeMap::new();
+    for commit in commits {
+        let date = iso_date(commit);
+        let group = groups
+            .entry((&commit.author, &commit.new_author))
+            .or_insert_with(|| AuthorGroup {
+                author: &commit.author,
+                new_author: &commit.new_author,
+                commits: 0,
+                first: date.clone(),
+                last: date.clone(),
+            });
+        group.commits += 1;
+        if date < group.first {
+            group.first = date.clone();
+        }
+        if date > group.last {
+            group.last = date;
+        }
+    }
+    let mut groups: Vec<_> = groups.into_values().collect();
+    groups.sort_by_key(|group| Reverse(group.commits));
+    groups
+}
+
+fn identity_change(
+    old: &str,
+    new: &str,
+    paint: &impl Fn(String, fn(&str) -> ColoredString) -> String,
+) -> String {
+    format!(
+        "{} → {}",
+        paint(truncate(old, IDENTITY_WIDTH), |s| s.red()),
+        paint(truncate(new, IDENTITY_WIDTH), |s| s.green())
+    )
+}
+
+fn iso_date(commit: &CommitInfo) -> String {
+    let timestamp = commit.iso_timestamp();
+    timestamp.get(..10).unwrap_or(&timestamp).to_string()
+}
+
+// Cuts text longer than `width` characters, ending it with an ellipsis
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(width - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,99 @@
+use crate::pattern::parse_identity;
+
+/// Trailer keys whose identities are rewritten unless configured otherwise
+pub const DEFAULT_TRAILER_KEYS: &[&str] = &[
+    "Co-authored-by",
+    "Signed-off-by",
+    "Reviewed-by",
+    "Acked-by",
+    "Tested-by",
+    "Reported-by",
+    "Suggested-by",
+    "Helped-by",
+];
+
+// A trailer identity that matched the configured patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrailerHit {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+    pub matched_pattern: String,
+}
+
+/// Rewrites identities in the trailer block of a commit message.
+///
+/// `replace` receives each trailer identity's name and email and returns the replacement
+/// identity and the pattern that matched. Returns `None` when no trailer changed.
+pub(crate) fn rewrite<F>(
+    message: &str,
+    keys: &[String],
+    mut replace: F,
+) -> Option<(String, Vec<TrailerHit>)>
+where
+    F: FnMut(&str, &str) -> Option<(String, String)>,
+{
+    let mut lines: Vec<String> = message.split('\n').map(str::to_string).collect();
+    let block = trailer_block(&lines)?;
+    let mut hits = Vec::new();
+
+    for line in &mut lines[block] {
+        let Some((key, value)) = split_trailer(line) else {
+            continue;
+        };
+        if !keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            continue;
+        }
+        let Some((name, email)) = parse_identity(value) else {
+            continue;
+        };
+        let Some((new, matched_pattern)) = replace(name, email) else {
+            continue;
+        };
+
+        let old = value.trim().to_string();
+        if new == old {
+            continue;
+        }
+        let key = key.to_string();
+        *line = format!("{}: {}", key, new);
+        hits.push(TrailerHit {
+            key,
+            old,
+            new,
+            matched_pattern,
+        });
+    }
+
+    if hits.is_empty() {
+        None
+    } else {
+        Some((lines.join("\n"), hits))
+    }
+}
+
+// Line range of the final paragraph when every line in it is a trailer or a continuation
+fn trailer_block(lines: &[String]) -> Option<std::ops::Range<usize>> {
+    let end = lines.iter().rposition(|l| !l.trim().is_empty())? + 1;
+    let start = lines[..end]
+        .iter()
+        .rposition(|l| l.trim().is_empty())
+        .map_or(0, |blank| blank + 1);
+
+    // The subject paragraph is never a trailer block
+    if start == 0 {
+        return None;
+    }
+
+    let is_trailers = lines[start..end].iter().enumerate().all(|(i, line)| {
+        split_trailer(line).is_some() || (i > 0 && line.starts_with(char::is_whitespace))
+    });
+    is_trailers.then_some(start..end)
+}
+
+// Splits "Key: value" where the key is a run of alphanumerics and dashes
+fn split_trailer(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let valid_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid_key.then_some((key, value))
+}
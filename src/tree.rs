@@ -0,0 +1,337 @@
+use crate::{replace::Replacer, Config, GitRebrandError};
+use anyhow::Result;
+use git2::{FileMode, ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// A path rename as written in the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRename {
+    /// Path prefix to replace (`oldco-sdk/`), or a regular expression when `regex` is set
+    pub from: String,
+    /// Replacement prefix; `$1` or `${name}` expand capture groups of regex rules
+    pub to: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+#[derive(Debug, Clone)]
+enum RenameRule {
+    Prefix(String, String),
+    Regex(Regex, String),
+}
+
+// Rename rules compiled once; the first rule matching a path wins
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathRenamer {
+    rules: Vec<RenameRule>,
+}
+
+impl PathRenamer {
+    pub(crate) fn new(renames: &[PathRename]) -> Result<Self, GitRebrandError> {
+        let rules = renames
+            .iter()
+            .map(|rename| {
+                if !rename.regex {
+                    return Ok(RenameRule::Prefix(rename.from.clone(), rename.to.clone()));
+                }
+                let regex = Regex::new(&rename.from).map_err(|e| {
+                    GitRebrandError::InvalidPattern(format!("{}: {}", rename.from, e))
+                })?;
+                Ok(RenameRule::Regex(regex, rename.to.clone()))
+            })
+            .collect::<Result<_, GitRebrandError>>()?;
+        Ok(Self { rules })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the new path, or `None` when no rule changes it
+    pub(crate) fn rename(&self, path: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| {
+            let renamed = match rule {
+                RenameRule::Prefix(from, to) => {
+                    format!("{}{}", to, path.strip_prefix(from.as_str())?)
+                },
+                RenameRule::Regex(regex, to) => {
+                    if !regex.is_match(path) {
+                        return None;
+                    }
+                    regex.replace_all(path, to.as_str()).into_owned()
+                },
+            };
+            Some(renamed).filter(|renamed| renamed != path)
+        })
+    }
+}
+
+// Everything that changes the trees of rewritten commits, compiled from the configuration
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TreeRules {
+    replacer: Replacer,
+    include: PathGlobs,
+    exclude: PathGlobs,
+    renamer: PathRenamer,
+}
+
+impl TreeRules {
+    pub(crate) fn new(config: &Config) -> Result<Self, GitRebrandError> {
+        Ok(Self {
+            replacer: Replacer::new(&config.content_replacements)?,
+            include: PathGlobs::new(&config.content_include)?,
+            exclude: PathGlobs::new(&config.content_exclude)?,
+            renamer: PathRenamer::new(&config.path_renames)?,
+        })
+    }
+}
+
+// Path globs matched like .gitignore entries: patterns without a '/' match the file name
+// at any depth, others match the full path with '*' stopping at directory boundaries
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathGlobs {
+    globs: Vec<(String, GlobMatcher)>,
+}
+
+impl PathGlobs {
+    pub(crate) fn new(patterns: &[String]) -> Result<Self, GitRebrandError> {
+        let globs = patterns
+            .iter()
+            .map(|pattern| {
+                let matcher = GlobBuilder::new(pattern.trim_start_matches('/'))
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| GitRebrandError::InvalidPattern(format!("{}: {}", pattern, e)))?
+                    .compile_matcher();
+                Ok((pattern.clone(), matcher))
+            })
+            .collect::<Result<_, GitRebrandError>>()?;
+        Ok(Self { globs })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    /// Returns the first pattern matching the path
+    pub(crate) fn first_match(&self, path: &str) -> Option<&str> {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        self.globs
+            .iter()
+            .find(|(pattern, matcher)| {
+                if pattern.contains('/') {
+                    matcher.is_match(path)
+                } else {
+                    matcher.is_match(file_name)
+                }
+            })
+            .map(|(pattern, _)| pattern.as_str())
+    }
+
+    pub(crate) fn is_match(&self, path: &str) -> bool {
+        self.first_match(path).is_some()
+    }
+}
+
+/// Rebuilds commit trees with transformed file contents and renamed paths.
+///
+/// Blobs are transformed at most once and whole subtrees are cached, so history that
+/// shares most of its tree between commits is only processed incrementally.
+pub(crate) struct TreeRewriter<'r> {
+    repo: &'r Repository,
+    rules: &'r TreeRules,
+    blobs: HashMap<Oid, Oid>,
+    trees: HashMap<(Oid, String), Oid>,
+    renamed: HashMap<Oid, Oid>,
+}
+
+impl<'r> TreeRewriter<'r> {
+    pub(crate) fn new(repo: &'r Repository, rules: &'r TreeRules) -> Self {
+        Self {
+            repo,
+            rules,
+            blobs: HashMap::new(),
+            trees: HashMap::new(),
+            renamed: HashMap::new(),
+        }
+    }
+
+    /// Returns the id of the rewritten tree, writing new objects as needed
+    pub(crate) fn rewrite(&mut self, tree: &Tree) -> Result<Oid> {
+        let id = if self.rules.replacer.is_empty() {
+            tree.id()
+        } else {
+            self.rewrite_tree(tree, "")?
+        };
+        if self.rules.renamer.is_empty() {
+            return Ok(id);
+        }
+        self.rename_paths(id)
+    }
+
+    // Flattens the tree, renames every path and rebuilds it from scratch
+    fn rename_paths(&mut self, id: Oid) -> Result<Oid> {
+        if let Some(cached) = self.renamed.get(&id) {
+            return Ok(*cached);
+        }
+
+        let tree = self.repo.find_tree(id)?;
+        let mut entries = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() != Some(ObjectType::Tree) {
+                if let Some(name) = entry.name() {
+                    entries.push((format!("{}{}", dir, name), entry.id(), entry.filemode()));
+                }
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        let mut root = TreeNode::default();
+        let mut sources: HashMap<String, String> = HashMap::new();
+        let mut changed = false;
+        for (path, oid, mode) in entries {
+            let new_path = match self.rules.renamer.rename(&path) {
+                Some(new_path) => {
+                    changed = true;
+                    new_path
+                },
+                None => path.clone(),
+            };
+            if let Some(other) = sources.insert(new_path.clone(), path.clone()) {
+                return Err(GitRebrandError::PathConflict(format!(
+                    "'{}' and '{}' would both be renamed to '{}'",
+                    other, path, new_path
+                ))
+                .into());
+            }
+            root.insert(&new_path, oid, mode)?;
+        }
+
+        let new_id = if changed { root.write(self.repo)? } else { id };
+        self.renamed.insert(id, new_id);
+        Ok(new_id)
+    }
+
+    fn rewrite_tree(&mut self, tree: &Tree, prefix: &str) -> Result<Oid> {
+        let key = (tree.id(), prefix.to_string());
+        if let Some(cached) = self.trees.get(&key) {
+            return Ok(*cached);
+        }
+
+        let mut builder = self.repo.treebuilder(Some(tree))?;
+        let mut changed = false;
+
+        for entry in tree.iter() {
+            let Some(name) = entry.name() else {
+                continue;
+            };
+            let path = format!("{}{}", prefix, name);
+            let new_id = match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    let subtree = self.repo.find_tree(entry.id())?;
+                    self.rewrite_tree(&subtree, &format!("{}/", path))?
+                },
+                Some(ObjectType::Blob) if entry.filemode() != i32::from(FileMode::Link) => {
+                    self.rewrite_blob(entry.id(), &path)?
+                },
+                _ => entry.id(),
+            };
+
+            if new_id != entry.id() {
+                changed = true;
+                builder.insert(name, new_id, entry.filemode())?;
+            }
+        }
+
+        let new_id = if changed { builder.write()? } else { tree.id() };
+        self.trees.insert(key, new_id);
+        Ok(new_id)
+    }
+
+    fn rewrite_blob(&mut self, id: Oid, path: &str) -> Result<Oid> {
+        let rules = self.rules;
+        let included = rules.include.is_empty() || rules.include.is_match(path);
+        if !included || rules.exclude.is_match(path) {
+            return Ok(id);
+        }
+        if let Some(cached) = self.blobs.get(&id) {
+            return Ok(*cached);
+        }
+
+        // Binary files (images, archives, ...) and non-UTF-8 text are never touched
+        let blob = self.repo.find_blob(id)?;
+        let transformed = if blob.is_binary() {
+            None
+        } else {
+            std::str::from_utf8(blob.content())
+                .ok()
+                .and_then(|text| rules.replacer.apply(text))
+        };
+        let new_id = match transformed {
+            Some(content) => self.repo.blob(content.as_bytes())?,
+            None => id,
+        };
+
+        self.blobs.insert(id, new_id);
+        Ok(new_id)
+    }
+}
+
+// Directory being rebuilt from a flat list of renamed paths
+#[derive(Default)]
+struct TreeNode {
+    entries: BTreeMap<String, TreeEntry>,
+}
+
+enum TreeEntry {
+    File(Oid, i32),
+    Dir(TreeNode),
+}
+
+impl TreeNode {
+    fn insert(&mut self, path: &str, oid: Oid, mode: i32) -> Result<()> {
+        let conflict = || {
+            GitRebrandError::PathConflict(format!(
+                "'{}' would be both a file and a directory",
+                path
+            ))
+        };
+        let mut node = self;
+        let mut components = path.split('/').peekable();
+        while let Some(name) = components.next() {
+            if components.peek().is_none() {
+                if node.entries.contains_key(name) {
+                    return Err(conflict().into());
+                }
+                node.entries
+                    .insert(name.to_string(), TreeEntry::File(oid, mode));
+                break;
+            }
+            let entry = node
+                .entries
+                .entry(name.to_string())
+                .or_insert_with(|| TreeEntry::Dir(TreeNode::default()));
+            node = match entry {
+                TreeEntry::Dir(dir) => dir,
+                TreeEntry::File(..) => return Err(conflict().into()),
+            };
+        }
+        Ok(())
+    }
+
+    fn write(&self, repo: &Repository) -> Result<Oid> {
+        let mut builder = repo.treebuilder(None)?;
+        for (name, entry) in &self.entries {
+            match entry {
+                TreeEntry::File(oid, mode) => builder.insert(name, *oid, *mode)?,
+                TreeEntry::Dir(dir) => {
+                    builder.insert(name, dir.write(repo)?, FileMode::Tree.into())?
+                },
+            };
+        }
+        Ok(builder.write()?)
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Config, GitRebrandError};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Days, NaiveDate};
+use git2::{Commit, Oid, Repository};
+use std::collections::HashSet;
+
+// Commits eligible for rewriting; everything outside is left byte-identical
+#[derive(Debug, Default)]
+pub(crate) struct CommitWindow {
+    commits: Option<HashSet<Oid>>,
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+impl CommitWindow {
+    /// Resolves the configured range, boundary commit and dates against the repository.
+    ///
+    /// `tips` are the selected refs, used as the upper end when no range is configured.
+    pub(crate) fn new(repo: &Repository, config: &Config, tips: &[Oid]) -> Result<Self> {
+        let commits = if config.range.is_some() || config.after_commit.is_some() {
+            let mut revwalk = repo.revwalk()?;
+            match &config.range {
+                Some(range) => {
+                    if !range.contains("..") {
+                        return Err(GitRebrandError::InvalidConfig(format!(
+                            "Invalid range '{}': expected <from>..<to>",
+                            range
+                        ))
+                        .into());
+                    }
+                    revwalk
+                        .push_range(range)
+                        .with_context(|| format!("Invalid range: {}", range))?;
+                },
+                None => {
+                    for tip in tips {
+                        revwalk.push(*tip)?;
+                    }
+                },
+            }
+            let mut commits = revwalk.collect::<Result<HashSet<_>, _>>()?;
+            if let Some(after) = &config.after_commit {
+                let boundary = repo
+                    .revparse_single(after)
+                    .and_then(|object| object.peel_to_commit())
+                    .with_context(|| format!("Invalid commit: {}", after))?
+                    .id();
+                let descendants = descendants(repo, &commits, boundary)?;
+                commits.retain(|id| descendants.contains(id));
+            }
+            Some(commits)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            commits,
+            since: config
+                .since
+                .as_deref()
+                .map(|date| parse_date(date, false))
+                .transpose()?,
+            until: config
+                .until
+                .as_deref()
+                .map(|date| parse_date(date, true))
+                .transpose()?,
+        })
+    }
+
+    pub(crate) fn contains(&self, commit: &Commit) -> bool {
+        // Like `git log`, dates are compared against the committer date
+        let time = commit.time().seconds();
+        self.commits
+            .as_ref()
+            .map_or(true, |commits| commits.contains(&commit.id()))
+            && self.since.map_or(true, |since| time >= since)
+            && self.until.map_or(true, |until| time <= until)
+    }
+}
+
+// Commits reachable from `tips` that descend from `boundary`. Hiding the boundary alone
+// would keep side branches forked before it and merged later, so parents are followed in
+// one topological pass: a commit descends from the boundary when one of its parents does.
+fn descendants(repo: &Repository, tips: &HashSet<Oid>, boundary: Oid) -> Result<HashSet<Oid>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    for tip in tips {
+        revwalk.push(*tip)?;
+    }
+    revwalk.hide(boundary)?;
+
+    let mut descendants = HashSet::new();
+    for id in revwalk {
+        let id = id?;
+        let commit = repo.find_commit(id)?;
+        if commit
+            .parent_ids()
+            .any(|parent| parent == boundary || descendants.contains(&parent))
+        {
+            descendants.insert(id);
+        }
+    }
+    Ok(descendants)
+}
+
+// Parses an RFC 3339 timestamp or a `YYYY-MM-DD` date (UTC) into unix seconds; a bare
+// date used as an upper bound covers the whole day
+fn parse_date(value: &str, end_of_day: bool) -> Result<i64, GitRebrandError> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        GitRebrandError::InvalidConfig(format!(
+            "Invalid date '{}': expected YYYY-MM-DD or an RFC 3339 timestamp",
+            value
+        ))
+    })?;
+    let start = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    Ok(if end_of_day {
+        (start + Days::new(1)).timestamp() - 1
+    } else {
+        start.timestamp()
+    })
+}
@@ -0,0 +1,151 @@
+use crate::GitRebrandError;
+use anyhow::Result;
+use git2::{Repository, StatusOptions};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, str::FromStr};
+
+/// How a rewrite treats uncommitted changes in the working tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirtyPolicy {
+    /// Refuse on any status entry, including untracked and ignored files
+    #[default]
+    Strict,
+    /// Only changes to tracked files block the rewrite
+    IgnoreUntracked,
+    /// Stash all changes before the rewrite and reapply them afterwards
+    AutoStash,
+}
+
+impl FromStr for DirtyPolicy {
+    type Err = GitRebrandError;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "strict" => Ok(Self::Strict),
+            "ignore-untracked" => Ok(Self::IgnoreUntracked),
+            "auto-stash" => Ok(Self::AutoStash),
+            _ => Err(GitRebrandError::InvalidConfig(format!(
+                "Unknown dirty policy '{}': expected strict, ignore-untracked or auto-stash",
+                policy
+            ))),
+        }
+    }
+}
+
+// A checkout other than the one the rewrite runs in, with the branch it has checked out
+pub(crate) struct Checkout {
+    pub(crate) repo: Repository,
+    pub(crate) branch: Option<String>,
+}
+
+impl Checkout {
+    pub(crate) fn path(&self) -> PathBuf {
+        self.repo
+            .workdir()
+            .unwrap_or_else(|| self.repo.path())
+            .to_path_buf()
+    }
+}
+
+/// Opens every other working tree of the repository: its linked worktrees, plus the main
+/// working tree when `repo` is itself a linked worktree. Missing worktrees are skipped.
+pub(crate) fn other_checkouts(repo: &Repository) -> Result<Vec<Checkout>> {
+    let mut repos = Vec::new();
+    if repo.is_worktree() {
+        let main = Repository::open(common_dir(repo))?;
+        if !main.is_bare() {
+            repos.push(main);
+        }
+    }
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        if let Err(e) = worktree.validate() {
+            warn!("Skipping worktree {}: {}", name, e.message());
+            continue;
+        }
+        let linked = Repository::open_from_worktree(&worktree)?;
+        if linked.workdir() != repo.workdir() {
+            repos.push(linked);
+        }
+    }
+
+    Ok(repos
+        .into_iter()
+        .map(|repo| {
+            let branch = repo
+                .head()
+                .ok()
+                .filter(|head| head.is_branch())
+                .and_then(|head| head.name().map(str::to_string));
+            Checkout { repo, branch }
+        })
+        .collect())
+}
+
+/// Refuses to move a branch that is checked out, with uncommitted changes, elsewhere
+pub(crate) fn ensure_clean(
+    checkouts: &[Checkout],
+    moving: &[&str],
+    tracked_only: bool,
+) -> Result<()> {
+    for checkout in checkouts {
+        let Some(branch) = checkout.branch.as_deref() else {
+            continue;
+        };
+        if moving.contains(&branch) && has_changes(&checkout.repo, tracked_only)? {
+            return Err(GitRebrandError::InvalidRepository(format!(
+                "Worktree at {} has uncommitted changes on {}",
+                checkout.path().display(),
+                branch
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Checks out the new commit in every worktree whose branch moved
+pub(crate) fn sync(checkouts: &[Checkout], moved: &[&str]) -> Result<()> {
+    for checkout in checkouts {
+        let Some(branch) = checkout.branch.as_deref() else {
+            continue;
+        };
+        if !moved.contains(&branch) {
+            continue;
+        }
+        let head = checkout.repo.head()?.peel_to_commit()?;
+        checkout
+            .repo
+            .reset(head.as_object(), git2::ResetType::Hard, None)?;
+        info!("Updated worktree at {}", checkout.path().display());
+    }
+    Ok(())
+}
+
+// The main `.git` directory, even when the repository was opened from a linked worktree
+pub(crate) fn common_dir(repo: &Repository) -> PathBuf {
+    let path = repo.path();
+    match fs::read_to_string(path.join("commondir")) {
+        Ok(common) => path.join(common.trim()),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Whether the working tree has changes; untracked and ignored files only count when
+// `tracked_only` is false
+pub(crate) fn has_changes(repo: &Repository, tracked_only: bool) -> Result<bool> {
+    if repo.is_bare() {
+        return Ok(false);
+    }
+    // Without options libgit2 also reports untracked and ignored files
+    let statuses = if tracked_only {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+        repo.statuses(Some(&mut options))?
+    } else {
+        repo.statuses(None)?
+    };
+    Ok(statuses.iter().any(|s| s.status() != git2::Status::CURRENT))
+}
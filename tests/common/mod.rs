@@ -1,5 +1,6 @@
 use anyhow::Result;
-use git2::{Repository, Signature};
+use git2::{Oid, Repository, Signature};
+use git_rebrand::Config;
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
@@ -39,9 +40,32 @@ impl TestRepo {
         Ok(path)
     }
 
-    pub fn commit_with_author(&self, message: &str, name: &str, email: &str) -> Result<()> {
+    pub fn commit_with_author(&self, message: &str, name: &str, email: &str) -> Result<Oid> {
         let sig = Signature::now(name, email)?;
+        self.commit_with_signature(message, &sig)
+    }
+
+    pub fn commit_with_author_at(
+        &self,
+        message: &str,
+        name: &str,
+        email: &str,
+        time: git2::Time,
+    ) -> Result<Oid> {
+        let sig = Signature::new(name, email, &time)?;
+        self.commit_with_signature(message, &sig)
+    }
 
+    pub fn commit_with_signature(&self, message: &str, sig: &Signature) -> Result<Oid> {
+        self.commit_with_identities(message, sig, sig)
+    }
+
+    pub fn commit_with_identities(
+        &self,
+        message: &str,
+        author: &Signature,
+        committer: &Signature,
+    ) -> Result<Oid> {
         // Create a file to commit
         let file_path = self.create_file(
             &format!("file_{}.txt", chrono::Utc::now().timestamp()),
@@ -57,9 +81,59 @@ impl TestRepo {
         let tree = self.repo.find_tree(tree_id)?;
         let parent = self.repo.head()?.peel_to_commit()?;
 
-        self.repo
-            .commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])?;
+        let oid = self
+            .repo
+            .commit(Some("HEAD"), author, committer, message, &tree, &[&parent])?;
+
+        Ok(oid)
+    }
+
+    pub fn commit_files(&self, message: &str, files: &[(&str, &[u8])]) -> Result<Oid> {
+        let mut index = self.repo.index()?;
+        for (name, content) in files {
+            let path = self.temp_dir.path().join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, content)?;
+            index.add_path(Path::new(name))?;
+        }
+        let tree_id = index.write_tree()?;
+        index.write()?;
+
+        let sig = Signature::now("Test User", "test@example.com")?;
+        let tree = self.repo.find_tree(tree_id)?;
+        let parent = self.repo.head()?.peel_to_commit()?;
+        Ok(self
+            .repo
+            .commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])?)
+    }
+
+    pub fn file_at(&self, commit: &git2::Commit, name: &str) -> Result<Vec<u8>> {
+        let entry = commit.tree()?.get_path(Path::new(name))?;
+        Ok(self.repo.find_blob(entry.id())?.content().to_vec())
+    }
+
+    pub fn head_history(&self) -> Result<Vec<git2::Commit<'_>>> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        revwalk
+            .map(|oid| Ok(self.repo.find_commit(oid?)?))
+            .collect()
+    }
+}
 
-        Ok(())
+/// Rewrites `Old Author <old@example.com>` to `New Author <new@example.com>` on the
+/// given refs (HEAD when empty), without backup refs
+pub fn old_author_config(repo: &TestRepo, refs: &[&str]) -> Config {
+    Config {
+        new_author_name: "New Author".to_string(),
+        new_author_email: "new@example.com".to_string(),
+        patterns: vec!["old@example.com".into()],
+        repo_path: repo.path().to_path_buf(),
+        create_backup: false,
+        refs: refs.iter().map(|r| r.to_string()).collect(),
+        ..Config::default()
     }
 }
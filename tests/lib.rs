@@ -1,10 +1,14 @@
 use anyhow::Result;
 use git2::Repository;
-use git_rebrand::{Config, GitRebrandError, GitRebrander};
+use git_rebrand::{
+    Config, GitRebrandError, GitRebrander, IdentityMapping, MatchSide, PathRename, Pattern,
+    TextReplacement,
+};
+use std::path::Path;
 use tempfile::TempDir;
 
 mod common;
-use common::TestRepo;
+use common::{old_author_config, TestRepo};
 
 #[test]
 fn test_empty_repository() -> Result<()> {
@@ -14,9 +18,9 @@ fn test_empty_repository() -> Result<()> {
     let config = Config {
         new_author_name: "New Author".to_string(),
         new_author_email: "new@example.com".to_string(),
-        patterns: vec!["old@example.com".to_string()],
+        patterns: vec!["old@example.com".into()],
         repo_path: temp.path().to_path_buf(),
-        create_backup: true,
+        ..Config::default()
     };
 
     let result = GitRebrander::new(config);
@@ -82,9 +86,9 @@ mod pattern_matching {
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["old@example.com".to_string()],
+            patterns: vec!["old@example.com".into()],
             repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            ..Config::default()
         };
 
         let rebrander = GitRebrander::new(config)?;
@@ -94,47 +98,1513 @@ mod pattern_matching {
         Ok(())
     }
 
+    #[test]
+    fn test_matches_carry_full_commit_id() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let oid = repo.commit_with_author("Test commit", "Old Author", "old@example.com")?;
+
+        let config = old_author_config(&repo, &[]);
+
+        let matches = GitRebrander::new(config)?.dry_run()?;
+        assert_eq!(matches[0].id, oid);
+        assert!(matches[0].short_id.len() >= 7);
+        assert!(oid.to_string().starts_with(&matches[0].short_id));
+        Ok(())
+    }
+
     #[test]
     fn test_email_domain_match() -> Result<()> {
         let repo = TestRepo::new()?;
-        repo.commit_with_author("Test commit", "Old Author", "test@oldcompany.com")?;
+        repo.commit_with_author("Test commit", "Old Author", "test@oldcompany.com")?;
+
+        let config = Config {
+            new_author_name: "New Author".to_string(),
+            new_author_email: "new@example.com".to_string(),
+            patterns: vec!["@oldcompany.com".into()],
+            repo_path: repo.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_insensitive_name_match() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Test commit", "Old Author Name", "test@example.com")?;
+
+        let config = Config {
+            new_author_name: "New Author".to_string(),
+            new_author_email: "new@example.com".to_string(),
+            patterns: vec!["old author".into()],
+            repo_path: repo.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        Ok(())
+    }
+}
+
+mod regex_patterns {
+    use super::*;
+    use std::fs;
+
+    fn regex_config(repo: &TestRepo, regex: &str) -> Config {
+        Config {
+            patterns: vec![Pattern::Regex {
+                regex: regex.to_string(),
+            }],
+            ..old_author_config(repo, &[])
+        }
+    }
+
+    #[test]
+    fn test_regex_pattern_from_config_file() -> Result<()> {
+        let temp = TempDir::new()?;
+        let config_path = temp.path().join("config.yml");
+
+        let config_content = r#"
+            new_author_name: "New Author"
+            new_author_email: "new@example.com"
+            patterns:
+              - "Old Name"
+              - regex: '^j(ohn|\.)doe@(old|legacy)\.com$'
+        "#;
+        fs::write(&config_path, config_content)?;
+
+        let config = Config::from_file(&config_path)?;
+        assert_eq!(config.patterns[0], Pattern::from("Old Name"));
+        assert_eq!(
+            config.patterns[1],
+            Pattern::Regex {
+                regex: r"^j(ohn|\.)doe@(old|legacy)\.com$".to_string()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_regex_matches_email() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Matched", "John Doe", "j.doe@legacy.com")?;
+        repo.commit_with_author("Not matched", "John Doe", "johndoe@legacy.company.com")?;
+
+        let rebrander =
+            GitRebrander::new(regex_config(&repo, r"^j(ohn|\.)doe@(old|legacy)\.com$"))?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].matched_pattern.starts_with("regex:"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_regex_rejected() -> Result<()> {
+        let repo = TestRepo::new()?;
+
+        let result = GitRebrander::new(regex_config(&repo, "^(unclosed"));
+        match result.unwrap_err().downcast::<GitRebrandError>()? {
+            GitRebrandError::InvalidPattern(message) => assert!(message.contains("^(unclosed")),
+            other => panic!("unexpected error: {other}"),
+        }
+        Ok(())
+    }
+}
+
+mod typed_patterns {
+    use super::*;
+    use std::fs;
+
+    fn typed_config(repo: &TestRepo, patterns: Vec<Pattern>) -> Config {
+        Config {
+            patterns,
+            ..old_author_config(repo, &[])
+        }
+    }
+
+    fn match_count(repo: &TestRepo, patterns: Vec<Pattern>) -> Result<usize> {
+        let rebrander = GitRebrander::new(typed_config(repo, patterns))?;
+        Ok(rebrander.dry_run()?.len())
+    }
+
+    #[test]
+    fn test_typed_patterns_from_config_file() -> Result<()> {
+        let temp = TempDir::new()?;
+        let config_path = temp.path().join("config.yml");
+
+        let config_content = r#"
+            new_author_name: "New Author"
+            new_author_email: "new@example.com"
+            patterns:
+              - "legacy substring"
+              - email: "john@old.com"
+              - domain: "old.com"
+              - name: "John"
+              - name_contains: "Doe"
+              - glob: "*@old-*.com"
+        "#;
+        fs::write(&config_path, config_content)?;
+
+        let config = Config::from_file(&config_path)?;
+        assert_eq!(
+            config.patterns,
+            vec![
+                Pattern::from("legacy substring"),
+                Pattern::Email {
+                    email: "john@old.com".to_string()
+                },
+                Pattern::Domain {
+                    domain: "old.com".to_string()
+                },
+                Pattern::Name {
+                    name: "John".to_string()
+                },
+                Pattern::NameContains {
+                    name_contains: "Doe".to_string()
+                },
+                Pattern::Glob {
+                    glob: "*@old-*.com".to_string()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_name_does_not_match_longer_name() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Johnny", "Johnny Appleseed", "johnny@example.com")?;
+        repo.commit_with_author("John", "john", "john@example.com")?;
+
+        let name = Pattern::Name {
+            name: "John".to_string(),
+        };
+        assert_eq!(match_count(&repo, vec![name])?, 1);
+        assert_eq!(match_count(&repo, vec!["John".into()])?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_domain_and_email_are_exact() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Exact", "Old Author", "dev@Old.com")?;
+        repo.commit_with_author("Lookalike", "Old Author", "dev@old.company.com")?;
+
+        let domain = Pattern::Domain {
+            domain: "@old.com".to_string(),
+        };
+        let email = Pattern::Email {
+            email: "DEV@old.com".to_string(),
+        };
+        assert_eq!(match_count(&repo, vec![domain])?, 1);
+        assert_eq!(match_count(&repo, vec![email])?, 1);
+        assert_eq!(match_count(&repo, vec!["@old.com".into()])?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_glob_pattern() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Matched", "Old Author", "dev@old-eu.com")?;
+        repo.commit_with_author("Not matched", "Old Author", "dev@old.com")?;
+
+        let glob = Pattern::Glob {
+            glob: "*@old-*.com".to_string(),
+        };
+        assert_eq!(match_count(&repo, vec![glob])?, 1);
+        Ok(())
+    }
+}
+
+mod identity_mappings {
+    use super::*;
+    use std::fs;
+
+    fn mapping(name: &str, email: &str, patterns: Vec<Pattern>) -> IdentityMapping {
+        IdentityMapping {
+            new_name: name.to_string(),
+            new_email: email.to_string(),
+            patterns,
+        }
+    }
+
+    #[test]
+    fn test_mappings_only_config_file() -> Result<()> {
+        let temp = TempDir::new()?;
+        let config_path = temp.path().join("config.yml");
+
+        let config_content = r#"
+            mappings:
+              - new_name: "Alice New"
+                new_email: "alice@new.com"
+                patterns:
+                  - email: "alice@old.com"
+              - new_name: "Bob New"
+                new_email: "bob@new.com"
+                patterns:
+                  - "bob"
+        "#;
+        fs::write(&config_path, config_content)?;
+
+        let config = Config::from_file(&config_path)?;
+        assert_eq!(config.mappings.len(), 2);
+        assert!(config.patterns.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_without_rules_rejected() -> Result<()> {
+        let temp = TempDir::new()?;
+        let config_path = temp.path().join("config.yml");
+        fs::write(&config_path, "create_backup: false\n")?;
+
+        assert!(Config::from_file(&config_path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_committer_only_config_file() -> Result<()> {
+        let temp = TempDir::new()?;
+        let config_path = temp.path().join("config.yml");
+        fs::write(
+            &config_path,
+            "committer:\n  new_name: Build Bot\n  new_email: ''\n  patterns:\n    - ci@old.com\n",
+        )?;
+
+        let config = Config::from_file(&config_path)?;
+        let committer = config.committer.unwrap();
+        assert_eq!(committer.new_name, "Build Bot");
+        assert!(committer.new_email.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_identities_in_one_pass() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Alice work", "Alice", "alice@old.com")?;
+        repo.commit_with_author("Bob work", "Bob", "bob@old.com")?;
+        repo.commit_with_author("Carol work", "Carol", "carol@old.com")?;
+
+        let config = Config {
+            mappings: vec![
+                mapping(
+                    "Alice New",
+                    "alice@new.com",
+                    vec![Pattern::Email {
+                        email: "alice@old.com".to_string(),
+                    }],
+                ),
+                mapping("Bob New", "bob@new.com", vec!["bob".into()]),
+                // Never reached for Alice or Bob: first match wins
+                mapping("Everyone", "team@new.com", vec!["@old.com".into()]),
+            ],
+            patterns: Vec::new(),
+            ..old_author_config(&repo, &[])
+        };
+
+        let rebrander = GitRebrander::new(config)?;
+        assert_eq!(rebrander.dry_run()?.len(), 3);
+        rebrander.run()?;
+
+        let emails: Vec<_> = repo
+            .head_history()?
+            .iter()
+            .take(3)
+            .map(|c| c.author().email().unwrap().to_string())
+            .collect();
+        assert_eq!(emails, vec!["team@new.com", "bob@new.com", "alice@new.com"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mapping_keeping_names_is_counted_once() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Alice work", "Alice", "alice@old.com")?;
+        repo.commit_with_author("Bob work", "Bob", "bob@old.com")?;
+
+        let config = Config {
+            mappings: vec![mapping("", "team@new.com", vec!["@old.com".into()])],
+            patterns: Vec::new(),
+            ..old_author_config(&repo, &[])
+        };
+
+        let commits = GitRebrander::new(config)?.dry_run()?;
+        assert_eq!(commits.len(), 2);
+        assert_ne!(commits[0].new_author, commits[1].new_author);
+        for commit in &commits {
+            assert_eq!(commit.mappings, vec!["* <team@new.com> (for @old.com)"]);
+        }
+        Ok(())
+    }
+}
+
+mod mailmap {
+    use super::*;
+    use git_rebrand::mailmap;
+    use std::fs;
+
+    #[test]
+    fn test_parse_all_line_forms() -> Result<()> {
+        let mappings = mailmap::parse(
+            "# comment\n\
+             Proper Name <commit@old.com>\n\
+             <proper@new.com> <other@old.com>\n\
+             Both New <both@new.com> <both@old.com>\n\
+             Pair New <pair@new.com> Pair Old <pair@old.com> # trailing\n",
+        )?;
+
+        assert_eq!(mappings.len(), 4);
+        assert_eq!(mappings[0].new_name, "Proper Name");
+        assert_eq!(mappings[0].new_email, "");
+        assert_eq!(mappings[1].new_name, "");
+        assert_eq!(mappings[1].new_email, "proper@new.com");
+        assert_eq!(
+            mappings[2].patterns,
+            vec![Pattern::Email {
+                email: "both@old.com".to_string()
+            }]
+        );
+        assert_eq!(
+            mappings[3].patterns,
+            vec![Pattern::Identity {
+                identity: "Pair Old <pair@old.com>".to_string()
+            }]
+        );
+        assert!(mailmap::parse("not a mailmap line").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_from_mailmap_file() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Name only", "old name", "keep@old.com")?;
+        repo.commit_with_author("Pair", "Pair Old", "pair@old.com")?;
+        let temp = TempDir::new()?;
+        let mailmap_path = temp.path().join("team.mailmap");
+        fs::write(
+            &mailmap_path,
+            "Kept Email <keep@old.com>\nPair New <pair@new.com> Pair Old <pair@old.com>\n",
+        )?;
+
+        let config = Config {
+            mailmap: Some(mailmap_path),
+            patterns: Vec::new(),
+            ..old_author_config(&repo, &[])
+        };
+
+        GitRebrander::new(config)?.run()?;
+
+        let history = repo.head_history()?;
+        assert_eq!(history[0].author().name(), Some("Pair New"));
+        assert_eq!(history[0].author().email(), Some("pair@new.com"));
+        assert_eq!(history[1].author().name(), Some("Kept Email"));
+        assert_eq!(history[1].author().email(), Some("keep@old.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_mailmap() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("First", "Old Author", "old@oldcompany.com")?;
+        repo.commit_with_author("Second", "Other Old", "other@oldcompany.com")?;
+
+        let config = Config {
+            patterns: vec![Pattern::Domain {
+                domain: "oldcompany.com".to_string(),
+            }],
+            ..old_author_config(&repo, &[])
+        };
+
+        // Exporting is read-only, so untracked files don't get in the way
+        repo.create_file("build.log", "output")?;
+        assert!(GitRebrander::new(config.clone()).is_err());
+        let exported = GitRebrander::open(config)?.export_mailmap()?;
+        let lines: Vec<_> = exported.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "New Author <new@example.com> Old Author <old@oldcompany.com>",
+                "New Author <new@example.com> Other Old <other@oldcompany.com>",
+            ]
+        );
+        assert_eq!(mailmap::parse(&exported)?.len(), 2);
+        Ok(())
+    }
+}
+
+mod trailers {
+    use super::*;
+
+    const MESSAGE: &str = "Add feature\n\nBody text.\n\n\
+                           Co-authored-by: Old Author <old@example.com>\n\
+                           Signed-off-by: Other <other@example.com>\n";
+
+    fn trailer_config(repo: &TestRepo, trailer_keys: Option<Vec<String>>) -> Config {
+        let mut config = old_author_config(repo, &[]);
+        if let Some(keys) = trailer_keys {
+            config.trailer_keys = keys;
+        }
+        config
+    }
+
+    #[test]
+    fn test_co_author_trailer_rewritten() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author(MESSAGE, "Other", "other@example.com")?;
+
+        let rebrander = GitRebrander::new(trailer_config(&repo, None))?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched_side, MatchSide::Neither);
+        assert_eq!(matches[0].trailer_hits.len(), 1);
+        assert_eq!(matches[0].trailer_hits[0].key, "Co-authored-by");
+        assert_eq!(
+            matches[0].trailer_hits[0].new,
+            "New Author <new@example.com>"
+        );
+        rebrander.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert_eq!(head_commit.author().email(), Some("other@example.com"));
+        assert_eq!(
+            head_commit.message(),
+            Some(
+                "Add feature\n\nBody text.\n\n\
+                 Co-authored-by: New Author <new@example.com>\n\
+                 Signed-off-by: Other <other@example.com>\n"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailer_keys_configurable() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author(MESSAGE, "Other", "other@example.com")?;
+
+        let keys = vec!["Signed-off-by".to_string()];
+        let rebrander = GitRebrander::new(trailer_config(&repo, Some(keys)))?;
+        assert!(matches!(
+            rebrander
+                .dry_run()
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::NoMatchingCommits
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_body_lines_are_not_trailers() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author(
+            "Co-authored-by: Old Author <old@example.com>\n\n\
+             Mentioned: Old Author <old@example.com> in passing\nbut not a trailer\n",
+            "Other",
+            "other@example.com",
+        )?;
+
+        let rebrander = GitRebrander::new(trailer_config(&repo, None))?;
+        assert!(rebrander.dry_run().is_err());
+        Ok(())
+    }
+}
+
+mod message_replacements {
+    use super::*;
+
+    fn replacement(find: &str, replace: &str, regex: bool) -> TextReplacement {
+        TextReplacement {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            regex,
+        }
+    }
+
+    #[test]
+    fn test_literal_and_regex_replacements() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author(
+            "Fix OldCo login (see https://oldco.com/issues/42)\n",
+            "Other",
+            "other@example.com",
+        )?;
+
+        let config = Config {
+            message_replacements: vec![
+                replacement("OldCo", "NewCo", false),
+                replacement(
+                    r"https://oldco\.com/issues/(\d+)",
+                    "https://newco.dev/i/$1",
+                    true,
+                ),
+                // Literal rules never expand capture references
+                replacement("login", "$1 sign-in", false),
+            ],
+            ..old_author_config(&repo, &[])
+        };
+
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched_side, MatchSide::Neither);
+        let change = matches[0].message_change.as_ref().unwrap();
+        assert_eq!(
+            change.preview(),
+            vec![
+                "- Fix OldCo login (see https://oldco.com/issues/42)",
+                "+ Fix NewCo $1 sign-in (see https://newco.dev/i/42)",
+            ]
+        );
+        rebrander.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert_eq!(
+            head_commit.message(),
+            Some("Fix NewCo $1 sign-in (see https://newco.dev/i/42)\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_replacement_regex_rejected() -> Result<()> {
+        let repo = TestRepo::new()?;
+
+        let config = Config {
+            message_replacements: vec![replacement("(unclosed", "x", true)],
+            ..old_author_config(&repo, &[])
+        };
+
+        assert!(matches!(
+            GitRebrander::new(config)
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::InvalidPattern(_)
+        ));
+        Ok(())
+    }
+}
+
+mod content_rewriting {
+    use super::*;
+
+    fn content_config(repo: &TestRepo, include: &[&str], exclude: &[&str]) -> Config {
+        Config {
+            content_replacements: vec![TextReplacement {
+                find: "OldCo".to_string(),
+                replace: "NewCo".to_string(),
+                regex: false,
+            }],
+            content_include: include.iter().map(|g| g.to_string()).collect(),
+            content_exclude: exclude.iter().map(|g| g.to_string()).collect(),
+            ..old_author_config(repo, &[])
+        }
+    }
+
+    #[test]
+    fn test_file_contents_rewritten_across_history() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo tools\n")])?;
+        repo.commit_files(
+            "Add sources",
+            &[
+                ("src/main.rs", b"// Copyright OldCo\n"),
+                ("logo.bin", b"OldCo\0\x01\x02"),
+            ],
+        )?;
+        repo.commit_files("Unrelated", &[("notes.txt", b"nothing here\n")])?;
+
+        let rebrander = GitRebrander::new(content_config(&repo, &[], &[]))?;
+        let matches = rebrander.dry_run()?;
+        // Every snapshot containing a rewritten file changes, not just the commits adding it
+        assert_eq!(matches.len(), 3);
+        assert!(matches.iter().all(|c| c.tree_changed));
+        assert!(matches.iter().all(|c| c.matched_side == MatchSide::Neither));
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        assert_eq!(history.len(), 4);
+        // Later commits keep the rewritten files they inherit
+        for commit in &history[..3] {
+            assert_eq!(repo.file_at(commit, "README.md")?, b"NewCo tools\n");
+        }
+        assert_eq!(
+            repo.file_at(&history[0], "src/main.rs")?,
+            b"// Copyright NewCo\n"
+        );
+        // Binary files are left untouched
+        assert_eq!(repo.file_at(&history[0], "logo.bin")?, b"OldCo\0\x01\x02");
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("README.md"))?,
+            "NewCo tools\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_and_exclude_globs() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files(
+            "Add files",
+            &[
+                ("README.md", b"OldCo\n"),
+                ("docs/guide.md", b"OldCo\n"),
+                ("docs/legal/LICENSE.md", b"OldCo\n"),
+                ("src/lib.rs", b"OldCo\n"),
+            ],
+        )?;
+
+        let rebrander = GitRebrander::new(content_config(&repo, &["*.md"], &["docs/legal/**"]))?;
+        rebrander.run()?;
+
+        let head = repo.head_history()?.remove(0);
+        assert_eq!(repo.file_at(&head, "README.md")?, b"NewCo\n");
+        assert_eq!(repo.file_at(&head, "docs/guide.md")?, b"NewCo\n");
+        assert_eq!(repo.file_at(&head, "docs/legal/LICENSE.md")?, b"OldCo\n");
+        assert_eq!(repo.file_at(&head, "src/lib.rs")?, b"OldCo\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_writes_no_objects() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo\n")])?;
+
+        let rebrander = GitRebrander::new(content_config(&repo, &[], &[]))?;
+        rebrander.dry_run()?;
+
+        let rewritten = git2::Oid::hash_object(git2::ObjectType::Blob, b"NewCo\n")?;
+        let git = git2::Repository::open(repo.path())?;
+        assert!(!git.odb()?.exists(rewritten));
+        Ok(())
+    }
+}
+
+mod path_renames {
+    use super::*;
+
+    fn rename(from: &str, to: &str, regex: bool) -> PathRename {
+        PathRename {
+            from: from.to_string(),
+            to: to.to_string(),
+            regex,
+        }
+    }
+
+    fn rename_config(repo: &TestRepo, path_renames: Vec<PathRename>) -> Config {
+        Config {
+            path_renames,
+            ..old_author_config(repo, &[])
+        }
+    }
+
+    #[test]
+    fn test_prefix_and_regex_renames() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files(
+            "Add sdk",
+            &[
+                ("oldco-sdk/lib.rs", b"lib\n"),
+                ("src/OldCoClient.rs", b"client\n"),
+                ("README.md", b"readme\n"),
+            ],
+        )?;
+        repo.commit_files("Update readme", &[("README.md", b"updated\n")])?;
+
+        let config = rename_config(
+            &repo,
+            vec![
+                rename("oldco-sdk/", "newco-sdk/", false),
+                rename(r"OldCo(\w*)\.rs$", "NewCo$1.rs", true),
+            ],
+        );
+        let rebrander = GitRebrander::new(config)?;
+        assert_eq!(rebrander.dry_run()?.len(), 2);
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        for commit in &history[..2] {
+            let tree = commit.tree()?;
+            assert!(tree.get_path(Path::new("oldco-sdk")).is_err());
+            assert!(tree.get_path(Path::new("newco-sdk/lib.rs")).is_ok());
+            assert!(tree.get_path(Path::new("src/OldCoClient.rs")).is_err());
+            assert!(tree.get_path(Path::new("src/NewCoClient.rs")).is_ok());
+        }
+        assert_eq!(repo.file_at(&history[0], "README.md")?, b"updated\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_colliding_renames_rejected() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files(
+            "Add both",
+            &[("oldco/config.toml", b"a\n"), ("newco/config.toml", b"b\n")],
+        )?;
+        let head = repo.head_history()?[0].id();
+
+        let rebrander = GitRebrander::new(rename_config(
+            &repo,
+            vec![rename("oldco/", "newco/", false)],
+        ))?;
+        let err = rebrander.run().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GitRebrandError>(),
+            Some(GitRebrandError::PathConflict(_))
+        ));
+        assert_eq!(repo.head_history()?[0].id(), head);
+        Ok(())
+    }
+}
+
+mod committer_rules {
+    use super::*;
+    use git2::Signature;
+
+    #[test]
+    fn test_author_rule_leaves_foreign_committer() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let author = Signature::now("Old Author", "old@example.com")?;
+        let committer = Signature::now("CI Bot", "ci@example.com")?;
+        repo.commit_with_identities("Applied patch", &author, &committer)?;
+
+        let config = old_author_config(&repo, &[]);
+
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched_side, MatchSide::Author);
+        rebrander.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert_eq!(head_commit.author().email(), Some("new@example.com"));
+        assert_eq!(head_commit.committer().email(), Some("ci@example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_separate_committer_rule() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let author = Signature::now("Contributor", "contributor@example.com")?;
+        let committer = Signature::now("Old Bot", "bot@oldcompany.com")?;
+        repo.commit_with_identities("Applied patch", &author, &committer)?;
+
+        let config = Config {
+            committer: Some(IdentityMapping {
+                new_name: "New Bot".to_string(),
+                new_email: "bot@newcompany.com".to_string(),
+                patterns: vec!["@oldcompany.com".into()],
+            }),
+            ..old_author_config(&repo, &[])
+        };
+
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched_side, MatchSide::Committer);
+        assert_eq!(matches[0].new_committer, "New Bot <bot@newcompany.com>");
+        rebrander.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert_eq!(
+            head_commit.author().email(),
+            Some("contributor@example.com")
+        );
+        assert_eq!(head_commit.committer().name(), Some("New Bot"));
+        assert_eq!(head_commit.committer().email(), Some("bot@newcompany.com"));
+        Ok(())
+    }
+}
+
+mod ref_selection {
+    use super::*;
+
+    #[test]
+    fn test_branches_and_tags_rewritten_once() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let shared = repo.commit_with_author("Shared", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        git_repo.branch("feature", &git_repo.find_commit(shared)?, false)?;
+        git_repo.reference("refs/tags/v1", shared, false, "test tag")?;
+        repo.commit_with_author("Main only", "Old Author", "old@example.com")?;
+
+        let rebrander =
+            GitRebrander::new(old_author_config(&repo, &["refs/heads/*", "refs/tags/*"]))?;
+        assert_eq!(rebrander.dry_run()?.len(), 2);
+        rebrander.run()?;
+
+        let feature = git_repo
+            .revparse_single("refs/heads/feature")?
+            .peel_to_commit()?;
+        let tag = git_repo.revparse_single("refs/tags/v1")?.peel_to_commit()?;
+        let main_parent = git_repo.head()?.peel_to_commit()?.parent(0)?;
+        assert_eq!(feature.author().email(), Some("new@example.com"));
+        assert_eq!(feature.id(), tag.id());
+        assert_eq!(feature.id(), main_parent.id());
+        Ok(())
+    }
+
+    #[test]
+    fn test_annotated_tags_recreated() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let initial = repo.head_history()?.remove(0).id();
+        let old = repo.commit_with_author("Release", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let time = git2::Time::new(1_600_000_000, -300);
+        let old_tagger = git2::Signature::new("Old Author", "old@example.com", &time)?;
+        let other_tagger = git2::Signature::now("Other", "other@example.com")?;
+        git_repo.tag(
+            "v1",
+            &git_repo.find_object(old, None)?,
+            &old_tagger,
+            "Release 1\n",
+            false,
+        )?;
+        let untouched = git_repo.tag(
+            "v0",
+            &git_repo.find_object(initial, None)?,
+            &other_tagger,
+            "Initial\n",
+            false,
+        )?;
+
+        let rebrander =
+            GitRebrander::new(old_author_config(&repo, &["refs/heads/*", "refs/tags/*"]))?;
+        rebrander.run()?;
+
+        let v1 = git_repo.revparse_single("refs/tags/v1")?.peel_to_tag()?;
+        let tagger = v1.tagger().unwrap();
+        assert_eq!(tagger.email(), Some("new@example.com"));
+        assert_eq!(tagger.when(), time);
+        assert_eq!(v1.message(), Some("Release 1\n"));
+        assert_eq!(v1.target_id(), git_repo.head()?.peel_to_commit()?.id());
+        assert_eq!(git_repo.refname_to_id("refs/tags/v0")?, untouched);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tags_follow_rewritten_head() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let initial = repo.head_history()?.remove(0).id();
+        let old = repo.commit_with_author("Release", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Later", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let tagger = git2::Signature::now("Old Author", "old@example.com")?;
+        let release = git_repo.find_object(old, None)?;
+        git_repo.tag("v1", &release, &tagger, "Release 1\n", false)?;
+        git_repo.tag_lightweight("v1-light", &release, false)?;
+        let untouched =
+            git_repo.tag_lightweight("v0", &git_repo.find_object(initial, None)?, false)?;
+
+        GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+
+        let new_release = git_repo.head()?.peel_to_commit()?.parent_id(0)?;
+        let v1 = git_repo.revparse_single("refs/tags/v1")?.peel_to_tag()?;
+        assert_eq!(v1.target_id(), new_release);
+        assert_eq!(v1.tagger().unwrap().email(), Some("new@example.com"));
+        assert_eq!(git_repo.refname_to_id("refs/tags/v1-light")?, new_release);
+        assert_eq!(git_repo.refname_to_id("refs/tags/v0")?, untouched);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tagger_only_rebrand() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let head = repo.commit_with_author("Release", "Other Author", "other@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let tagger = git2::Signature::now("Old Author", "old@example.com")?;
+        git_repo.tag(
+            "v1",
+            &git_repo.find_object(head, None)?,
+            &tagger,
+            "Release 1\n",
+            false,
+        )?;
+
+        let rebrander = GitRebrander::new(old_author_config(&repo, &[]))?;
+        let preview = rebrander.preview()?;
+        assert!(preview.commits.is_empty());
+        assert_eq!(preview.tags.len(), 1);
+        assert_eq!(preview.tags[0].name, "refs/tags/v1");
+        assert_eq!(preview.tags[0].new_tagger, "New Author <new@example.com>");
+
+        let result = rebrander.run()?;
+        assert_eq!(result.tags.len(), 1);
+        let v1 = git_repo.revparse_single("refs/tags/v1")?.peel_to_tag()?;
+        assert_eq!(v1.tagger().unwrap().email(), Some("new@example.com"));
+        assert_eq!(v1.target_id(), head);
+        assert_eq!(git_repo.head()?.peel_to_commit()?.id(), head);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unselected_branch_untouched() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let shared = repo.commit_with_author("Shared", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        git_repo.branch("feature", &git_repo.find_commit(shared)?, false)?;
+
+        GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+
+        let feature = git_repo.revparse_single("refs/heads/feature")?;
+        assert_eq!(feature.id(), shared);
+        assert_eq!(
+            git_repo.head()?.peel_to_commit()?.author().email(),
+            Some("new@example.com")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notes_refs_are_not_rewritten() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let old = repo.commit_with_author("Annotated", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let notes_author = git2::Signature::now("Old Author", "old@example.com")?;
+        git_repo.note(&notes_author, &notes_author, None, old, "Reviewed", false)?;
+        let notes = git_repo.refname_to_id("refs/notes/commits")?;
+
+        let rebrander = GitRebrander::new(old_author_config(&repo, &["refs/*"]))?;
+        assert_eq!(rebrander.dry_run()?.len(), 1);
+        rebrander.run()?;
+
+        assert_eq!(git_repo.refname_to_id("refs/notes/commits")?, notes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unmatched_glob_is_rejected() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Shared", "Old Author", "old@example.com")?;
+
+        let rebrander = GitRebrander::new(old_author_config(&repo, &["refs/heads/release/*"]))?;
+        assert!(matches!(
+            rebrander
+                .dry_run()
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::InvalidConfig(_)
+        ));
+        Ok(())
+    }
+}
+
+mod ref_transaction {
+    use super::*;
+
+    #[test]
+    fn test_reflog_names_the_run() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let shared = repo.commit_with_author("Shared", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        git_repo.branch("feature", &git_repo.find_commit(shared)?, false)?;
+
+        GitRebrander::new(old_author_config(&repo, &["refs/heads/*"]))?.run()?;
+
+        let run_id = git_rebrand::journal::history(repo.path())?[0].id.clone();
+        let reflog = git_repo.reflog("refs/heads/feature")?;
+        assert_eq!(
+            reflog
+                .get(0)
+                .and_then(|entry| entry.message().map(str::to_string)),
+            Some(format!("rebrand: {}", run_id))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_locked_ref_moves_nothing() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let shared = repo.commit_with_author("Shared", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        git_repo.branch("feature", &git_repo.find_commit(shared)?, false)?;
+        let head = git_repo.head()?.target();
+
+        // Another process holding the lock on one ref must not leave the other moved
+        std::fs::write(git_repo.path().join("refs/heads/feature.lock"), "")?;
+        assert!(
+            GitRebrander::new(old_author_config(&repo, &["refs/heads/*"]))?
+                .run()
+                .is_err()
+        );
+
+        assert_eq!(git_repo.head()?.target(), head);
+        assert_eq!(git_repo.refname_to_id("refs/heads/feature")?, shared);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detached_head_rewritten() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let commit = repo.commit_with_author("Detached", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        git_repo.set_head_detached(commit)?;
+
+        let config = Config {
+            refs: Vec::new(),
+            ..old_author_config(&repo, &["refs/heads/*"])
+        };
+        GitRebrander::new(config)?.run()?;
+
+        assert!(git_repo.head_detached()?);
+        let head = git_repo.head()?.peel_to_commit()?;
+        assert_eq!(head.author().email(), Some("new@example.com"));
+        Ok(())
+    }
+}
+
+mod bare_repositories {
+    use super::*;
+
+    #[test]
+    fn test_bare_mirror_rewritten() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Old work", "Old Author", "old@example.com")?;
+        let mirror_dir = TempDir::new()?;
+        let mirror = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(repo.path().to_str().unwrap_or_default(), mirror_dir.path())?;
+        assert!(mirror.is_bare());
 
         let config = Config {
-            new_author_name: "New Author".to_string(),
-            new_author_email: "new@example.com".to_string(),
-            patterns: vec!["@oldcompany.com".to_string()],
-            repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            repo_path: mirror_dir.path().to_path_buf(),
+            ..old_author_config(&repo, &["refs/heads/*"])
+        };
+        GitRebrander::new(config)?.run()?;
+
+        let head = mirror.head()?.peel_to_commit()?;
+        assert_eq!(head.author().email(), Some("new@example.com"));
+        // The source repository is untouched
+        let source = repo.head_history()?.remove(0);
+        assert_eq!(source.author().email(), Some("old@example.com"));
+
+        git_rebrand::journal::undo(mirror_dir.path(), None)?;
+        let head = mirror.head()?.peel_to_commit()?;
+        assert_eq!(head.id(), source.id());
+        Ok(())
+    }
+}
+
+mod worktrees {
+    use super::*;
+
+    // Checks out a new `feature` branch at HEAD in a linked worktree
+    fn add_worktree(repo: &TestRepo, dir: &TempDir) -> Result<Repository> {
+        let git_repo = Repository::open(repo.path())?;
+        let head = git_repo.head()?.peel_to_commit()?;
+        let branch = git_repo.branch("feature", &head, false)?;
+        let mut options = git2::WorktreeAddOptions::new();
+        options.reference(Some(branch.get()));
+        let worktree = git_repo.worktree("feature", &dir.path().join("feature"), Some(&options))?;
+        Ok(Repository::open_from_worktree(&worktree)?)
+    }
+
+    #[test]
+    fn test_clean_worktree_follows_rewritten_branch() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo\n")])?;
+        repo.commit_with_author("Old work", "Old Author", "old@example.com")?;
+        let dir = TempDir::new()?;
+        let linked = add_worktree(&repo, &dir)?;
+
+        let config = Config {
+            content_replacements: vec![TextReplacement {
+                find: "OldCo".to_string(),
+                replace: "NewCo".to_string(),
+                regex: false,
+            }],
+            ..old_author_config(&repo, &["refs/heads/*"])
         };
+        GitRebrander::new(config)?.run()?;
 
+        let head = linked.head()?.peel_to_commit()?;
+        assert_eq!(head.author().email(), Some("new@example.com"));
+        let readme = std::fs::read_to_string(dir.path().join("feature/README.md"))?;
+        assert_eq!(readme, "NewCo\n");
+        assert_eq!(linked.statuses(None)?.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dirty_worktree_on_rewritten_branch_refused() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"readme\n")])?;
+        repo.commit_with_author("Old work", "Old Author", "old@example.com")?;
+        let dir = TempDir::new()?;
+        let linked = add_worktree(&repo, &dir)?;
+        let feature = linked.head()?.target();
+        std::fs::write(dir.path().join("feature/README.md"), "local edit\n")?;
+
+        let err = GitRebrander::new(old_author_config(&repo, &["refs/heads/*"]))?
+            .run()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast::<GitRebrandError>()?,
+            GitRebrandError::InvalidRepository(message) if message.contains("feature")
+        ));
+        assert_eq!(linked.head()?.target(), feature);
+
+        // Rewriting only the main branch leaves the dirty worktree alone
+        let head_name = Repository::open(repo.path())?
+            .head()?
+            .name()
+            .unwrap_or_default()
+            .to_string();
+        let config = Config {
+            refs: vec![head_name],
+            ..old_author_config(&repo, &["refs/heads/*"])
+        };
+        GitRebrander::new(config)?.run()?;
+        assert_eq!(linked.head()?.target(), feature);
+        Ok(())
+    }
+}
+
+mod commit_window {
+    use super::*;
+
+    #[test]
+    fn test_range_leaves_older_commits_identical() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let before = repo.commit_with_author("Before", "Old Author", "old@example.com")?;
+        Repository::open(repo.path())?.reference("refs/tags/v1.0", before, false, "tag")?;
+        repo.commit_with_author("After", "Old Author", "old@example.com")?;
+
+        let config = Config {
+            range: Some("v1.0..HEAD".to_string()),
+            ..old_author_config(&repo, &[])
+        };
+        let rebrander = GitRebrander::new(config)?;
+        assert_eq!(rebrander.dry_run()?.len(), 1);
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        assert_eq!(history[0].author().email(), Some("new@example.com"));
+        assert_eq!(history[1].id(), before);
+        assert_eq!(history[1].author().email(), Some("old@example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_window_reparents_later_commits() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let at = |seconds| git2::Time::new(seconds, 0);
+        // 2020-09-13, 2022-01-01 and 2024-01-01
+        let early = repo.commit_with_author_at(
+            "Early",
+            "Old Author",
+            "old@example.com",
+            at(1_600_000_000),
+        )?;
+        repo.commit_with_author_at("Inside", "Old Author", "old@example.com", at(1_641_000_000))?;
+        repo.commit_with_author_at("Late", "Old Author", "old@example.com", at(1_704_067_200))?;
+
+        let config = Config {
+            since: Some("2021-01-01".to_string()),
+            until: Some("2022-12-31".to_string()),
+            ..old_author_config(&repo, &[])
+        };
         let rebrander = GitRebrander::new(config)?;
         let matches = rebrander.dry_run()?;
         assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].author, "Old Author <old@example.com>");
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        assert_eq!(history[2].id(), early);
+        assert_eq!(history[1].author().email(), Some("new@example.com"));
+        // Commits after the window keep their identity but sit on the rewritten parent
+        assert_eq!(history[0].author().email(), Some("old@example.com"));
+        assert_eq!(history[0].parent_id(0)?, history[1].id());
         Ok(())
     }
 
     #[test]
-    fn test_case_insensitive_name_match() -> Result<()> {
+    fn test_after_commit_and_invalid_dates() -> Result<()> {
         let repo = TestRepo::new()?;
-        repo.commit_with_author("Test commit", "Old Author Name", "test@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let fork = git_repo.head()?.peel_to_commit()?;
+        let boundary = repo.commit_with_author("Boundary", "Old Author", "old@example.com")?;
+        let after = repo.commit_with_author("After", "Old Author", "old@example.com")?;
+
+        // A side branch forked before the boundary and merged after it
+        let old = git2::Signature::now("Old Author", "old@example.com")?;
+        let side = git_repo.commit(None, &old, &old, "Side", &fork.tree()?, &[&fork])?;
+        let head = git_repo.head()?.peel_to_commit()?;
+        let merge = git_repo.commit(
+            Some("HEAD"),
+            &old,
+            &old,
+            "Merge side",
+            &head.tree()?,
+            &[&head, &git_repo.find_commit(side)?],
+        )?;
 
         let config = Config {
-            new_author_name: "New Author".to_string(),
-            new_author_email: "new@example.com".to_string(),
-            patterns: vec!["old author".to_string()],
-            repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            after_commit: Some(boundary.to_string()),
+            ..old_author_config(&repo, &[])
+        };
+        let mut ids: Vec<_> = GitRebrander::new(config)?
+            .dry_run()?
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        ids.sort();
+        let mut expected = vec![after, merge];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        let config = Config {
+            since: Some("last tuesday".to_string()),
+            ..old_author_config(&repo, &[])
         };
+        assert!(matches!(
+            GitRebrander::new(config)?
+                .dry_run()
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::InvalidConfig(_)
+        ));
+        Ok(())
+    }
+}
+
+mod path_scope {
+    use super::*;
+
+    #[test]
+    fn test_only_commits_touching_scope_match() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Vendor drop", &[("vendor/oldco/api.c", b"api\n")])?;
+        repo.commit_files("App change", &[("src/main.c", b"main\n")])?;
+        repo.commit_files(
+            "Both",
+            &[("vendor/oldco/api.h", b"api\n"), ("README.md", b"readme\n")],
+        )?;
 
+        // TestRepo::commit_files commits as "Test User <test@example.com>"
+        let config = Config {
+            new_author_name: "OldCo Vendor".to_string(),
+            new_author_email: "vendor@oldco.com".to_string(),
+            patterns: vec!["test@example.com".into()],
+            paths: vec!["vendor/oldco/**".to_string()],
+            ..old_author_config(&repo, &[])
+        };
         let rebrander = GitRebrander::new(config)?;
         let matches = rebrander.dry_run()?;
-        assert_eq!(matches.len(), 1);
+        let mut paths: Vec<_> = matches.iter().map(|c| c.matched_paths.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![vec!["vendor/oldco/api.c"], vec!["vendor/oldco/api.h"]]
+        );
+        rebrander.run()?;
+
+        let emails: Vec<_> = repo
+            .head_history()?
+            .iter()
+            .map(|c| c.author().email().unwrap_or("").to_string())
+            .collect();
+        assert_eq!(
+            emails,
+            vec![
+                "vendor@oldco.com",
+                "test@example.com",
+                "vendor@oldco.com",
+                "test@example.com",
+            ]
+        );
+        Ok(())
+    }
+}
+
+mod reports {
+    use super::*;
+    use git_rebrand::report::{self, OutputFormat, TableOptions};
+    use serde_json::Value;
+
+    #[test]
+    fn test_json_report_of_dry_run() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let time = git2::Time::new(1_700_000_000, 0);
+        let id = repo.commit_with_author_at("Old work", "Old Author", "old@example.com", time)?;
+        repo.commit_with_author("Other work", "Someone", "someone@example.com")?;
+
+        let commits = GitRebrander::new(old_author_config(&repo, &[]))?.dry_run()?;
+        let mut out = Vec::new();
+        report::write(&mut out, OutputFormat::Json, &commits, &[], None)?;
+        let report: Value = serde_json::from_slice(&out)?;
+
+        let commit = &report["commits"][0];
+        assert_eq!(commit["id"], id.to_string());
+        assert_eq!(commit["timestamp"], "2023-11-14T22:13:20Z");
+        assert_eq!(commit["author"]["old"], "Old Author <old@example.com>");
+        assert_eq!(commit["author"]["new"], "New Author <new@example.com>");
+        assert_eq!(commit["matched_pattern"], "old@example.com");
+        assert_eq!(
+            commit["match_location"],
+            serde_json::json!(["author", "committer"])
+        );
+
+        let summary = &report["summary"];
+        assert_eq!(summary["mode"], "dry-run");
+        assert_eq!(summary["commits"], 1);
+        assert_eq!(
+            summary["mappings"]["New Author <new@example.com> (for old@example.com)"],
+            1
+        );
+        assert_eq!(summary["run_id"], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_report_of_run() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("First", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Second", "Old Author", "old@example.com")?;
+
+        let result = GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+        let mut out = Vec::new();
+        report::write(
+            &mut out,
+            OutputFormat::Ndjson,
+            &result.commits,
+            &result.tags,
+            Some(&result.run),
+        )?;
+        let lines = out
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        let types: Vec<_> = lines.iter().map(|l| l["type"].clone()).collect();
+        assert_eq!(types, vec!["commit", "commit", "summary"]);
+        let summary = &lines[2];
+        assert_eq!(summary["mode"], "run");
+        assert_eq!(summary["commits"], 2);
+        assert_eq!(summary["run_id"], result.run.id);
+        let head = Repository::open(repo.path())?
+            .head()?
+            .peel_to_commit()?
+            .id();
+        assert_eq!(summary["refs"][0]["new"], head.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_is_truncated_to_limit() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let time = git2::Time::new(1_700_000_000, 0);
+        for i in 0..3 {
+            let message = format!("Change {} {}", i, "with a very long subject ".repeat(4));
+            repo.commit_with_author_at(&message, "Old Author", "old@example.com", time)?;
+        }
+
+        let commits = GitRebrander::new(old_author_config(&repo, &[]))?.dry_run()?;
+        let options = TableOptions {
+            limit: 2,
+            ..TableOptions::default()
+        };
+        let mut out = Vec::new();
+        report::write_table(&mut out, &commits, &[], &options)?;
+        let table = String::from_utf8(out)?;
+
+        assert!(table.contains(&commits[0].short_id));
+        assert!(table.contains("2023-11-14"));
+        assert!(table.contains("Old Author <old@example.com> → New Author <new@example.com>"));
+        assert!(table.contains("with a very long subject with a very lon…"));
+        assert!(!table.contains(&commits[2].short_id));
+        assert!(table.contains("1 more commits not shown"));
+        assert!(table.contains("3 commits would be rewritten"));
+        assert!(!table.contains('\u{1b}'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_grouped_by_author() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("First", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Second", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Third", "Old Bot", "bot@example.com")?;
+
+        let mut config = old_author_config(&repo, &[]);
+        config.patterns.push("bot@example.com".into());
+        let commits = GitRebrander::new(config)?.dry_run()?;
+        let options = TableOptions {
+            group_by_author: true,
+            ..TableOptions::default()
+        };
+        let mut out = Vec::new();
+        report::write_table(&mut out, &commits, &[], &options)?;
+        let table = String::from_utf8(out)?;
+
+        let rows: Vec<_> = table.lines().filter(|l| l.contains(" | ")).collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].contains("Old Author <old@example.com>") && rows[1].contains(" 2 "));
+        assert!(rows[2].contains("Old Bot <bot@example.com>") && rows[2].contains(" 1 "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_replacements_apply_outside_scope() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo tools\n")])?;
+        repo.commit_files("Vendor drop", &[("vendor/oldco/api.c", b"api\n")])?;
+        repo.commit_files("App change", &[("src/main.c", b"main\n")])?;
+
+        let config = Config {
+            new_author_name: "OldCo Vendor".to_string(),
+            new_author_email: "vendor@oldco.com".to_string(),
+            patterns: vec!["test@example.com".into()],
+            paths: vec!["vendor/oldco/**".to_string()],
+            content_replacements: vec![TextReplacement {
+                find: "OldCo".to_string(),
+                replace: "NewCo".to_string(),
+                regex: false,
+            }],
+            ..old_author_config(&repo, &[])
+        };
+        let rebrander = GitRebrander::new(config)?;
+        let matches = rebrander.dry_run()?;
+        assert_eq!(matches.len(), 3);
+        assert_eq!(
+            matches
+                .iter()
+                .filter(|c| c.matched_side != MatchSide::Neither)
+                .count(),
+            1
+        );
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        for commit in &history[..3] {
+            assert_eq!(repo.file_at(commit, "README.md")?, b"NewCo tools\n");
+        }
+        let emails: Vec<_> = history[..3]
+            .iter()
+            .map(|c| c.author().email().unwrap_or("").to_string())
+            .collect();
+        assert_eq!(
+            emails,
+            vec!["test@example.com", "vendor@oldco.com", "test@example.com"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("README.md"))?,
+            "NewCo tools\n"
+        );
         Ok(())
     }
 }
 
 mod history_rewriting {
     use super::*;
+    use git_rebrand::backup::{backup_ref_name, prune, restore_bundle, BACKUP_NAMESPACE};
 
     #[test]
     fn test_successful_rewrite() -> Result<()> {
@@ -144,9 +1614,9 @@ mod history_rewriting {
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["old@example.com".to_string()],
+            patterns: vec!["old@example.com".into()],
             repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            ..Config::default()
         };
 
         let rebrander = GitRebrander::new(config)?;
@@ -161,29 +1631,180 @@ mod history_rewriting {
     }
 
     #[test]
-    fn test_backup_branch_creation() -> Result<()> {
+    fn test_descendants_are_reparented() -> Result<()> {
         let repo = TestRepo::new()?;
-        repo.commit_with_author("Test commit", "Old Author", "old@example.com")?;
+        repo.commit_with_author("First old", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Other", "Other Author", "other@example.com")?;
+        repo.commit_with_author("Second old", "Old Author", "old@example.com")?;
+        repo.commit_with_author("Tip", "Other Author", "other@example.com")?;
+
+        let config = old_author_config(&repo, &[]);
+
+        let rebrander = GitRebrander::new(config)?;
+        rebrander.run()?;
+
+        let history = repo.head_history()?;
+        let messages: Vec<_> = history.iter().map(|c| c.summary().unwrap()).collect();
+        assert_eq!(
+            messages,
+            vec!["Tip", "Second old", "Other", "First old", "Initial commit"]
+        );
+        assert!(history
+            .iter()
+            .all(|c| c.author().email() != Some("old@example.com")));
+        assert_eq!(history[0].author().email(), Some("other@example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_encoding_preserved() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Old", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let parent = git_repo.head()?.peel_to_commit()?;
+        // A Latin-1 message that libgit2's commit API can't write
+        let mut raw = format!(
+            "tree {}\nparent {}\nauthor Other <other@example.com> 1600000000 +0000\n\
+             committer Other <other@example.com> 1600000000 +0000\nencoding ISO-8859-1\n\n",
+            parent.tree_id(),
+            parent.id()
+        )
+        .into_bytes();
+        raw.extend_from_slice(b"caf\xe9\n");
+        let tip = git_repo.odb()?.write(git2::ObjectType::Commit, &raw)?;
+        let branch = git_repo.head()?.name().unwrap_or_default().to_string();
+        git_repo.reference(&branch, tip, true, "latin-1 commit")?;
+
+        GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+
+        let history = repo.head_history()?;
+        assert_ne!(history[0].id(), tip);
+        assert_eq!(history[0].message_raw_bytes(), b"caf\xe9\n");
+        assert_eq!(history[0].message_encoding(), Some("ISO-8859-1"));
+        assert_eq!(history[1].author().email(), Some("new@example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_original_dates_preserved() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let time = git2::Time::new(1_600_000_000, 120);
+        repo.commit_with_author_at("Dated commit", "Old Author", "old@example.com", time)?;
+
+        let config = old_author_config(&repo, &[]);
+
+        GitRebrander::new(config)?.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert_eq!(head_commit.author().email(), Some("new@example.com"));
+        assert_eq!(head_commit.author().when(), time);
+        assert_eq!(head_commit.committer().when(), time);
+        Ok(())
+    }
+
+    #[test]
+    fn test_redate_uses_current_time() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let time = git2::Time::new(1_600_000_000, 120);
+        repo.commit_with_author_at("Dated commit", "Old Author", "old@example.com", time)?;
+
+        let config = Config {
+            redate: true,
+            ..old_author_config(&repo, &[])
+        };
+
+        GitRebrander::new(config)?.run()?;
+
+        let head_commit = repo.head_history()?.remove(0);
+        assert!(head_commit.author().when().seconds() > time.seconds());
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_refs_creation() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let original = repo.commit_with_author("Test commit", "Old Author", "old@example.com")?;
+        let git_repo = Repository::open(repo.path())?;
+        let head_name = git_repo.head()?.name().unwrap_or_default().to_string();
+        let tag = git_repo.tag(
+            "v1",
+            &git_repo.find_object(original, None)?,
+            &git2::Signature::now("Old Author", "old@example.com")?,
+            "Release",
+            false,
+        )?;
 
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["old@example.com".to_string()],
+            patterns: vec!["old@example.com".into()],
+            refs: vec!["refs/heads/*".to_string(), "refs/tags/*".to_string()],
             repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            ..Config::default()
         };
 
         let rebrander = GitRebrander::new(config)?;
         rebrander.run()?;
 
-        // Verify backup branch exists
-        let git_repo = Repository::open(repo.path())?;
-        let branches: Vec<_> = git_repo
-            .branches(None)?
-            .map(|b| b.unwrap().0.name().unwrap().unwrap().to_string())
-            .collect();
+        // Backups live outside refs/heads and keep the original objects
+        let run_id = git_rebrand::journal::history(repo.path())?[0].id.clone();
+        let branch_backup = backup_ref_name(&run_id, &head_name);
+        let tag_backup = backup_ref_name(&run_id, "refs/tags/v1");
+        assert_eq!(git_repo.refname_to_id(&branch_backup)?, original);
+        assert_eq!(git_repo.refname_to_id(&tag_backup)?, tag);
+
+        assert_eq!(git_repo.branches(None)?.count(), 1);
+
+        // A later run over all refs leaves the backups alone
+        let config = Config {
+            new_author_name: "Newer Author".to_string(),
+            new_author_email: "newer@example.com".to_string(),
+            patterns: vec!["new@example.com".into()],
+            refs: vec!["refs/*".to_string()],
+            ..old_author_config(&repo, &[])
+        };
+        GitRebrander::new(config)?.run()?;
+        assert_eq!(git_repo.refname_to_id(&branch_backup)?, original);
+
+        // Pruning by age only removes sufficiently old runs
+        assert!(prune(repo.path(), "1d")?.is_empty());
+        assert_eq!(prune(repo.path(), "0s")?, vec![run_id]);
+        assert!(git_repo.find_reference(&branch_backup).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_backup_restores_elsewhere() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let original = repo.commit_with_author("Test commit", "Old Author", "old@example.com")?;
+        let backups = TempDir::new()?;
 
-        assert!(branches.iter().any(|name| name.starts_with("backup_")));
+        let config = Config {
+            backup_bundle: Some(backups.path().to_path_buf()),
+            ..old_author_config(&repo, &[])
+        };
+        GitRebrander::new(config)?.run()?;
+
+        let run_id = git_rebrand::journal::history(repo.path())?[0].id.clone();
+        let bundle = backups.path().join(format!("rebrand-{}.bundle", run_id));
+        assert!(std::fs::read(&bundle)?.starts_with(b"# v2 git bundle\n"));
+
+        // The bundle carries the whole original history, so it restores into another clone
+        let other = TestRepo::new()?;
+        let restored = restore_bundle(other.path(), &bundle)?;
+        let head_name = Repository::open(repo.path())?
+            .head()?
+            .name()
+            .unwrap_or_default()
+            .to_string();
+        assert_eq!(restored, vec![head_name]);
+        let history = other.head_history()?;
+        assert_eq!(history[0].id(), original);
+        assert_eq!(history[0].author().email(), Some("old@example.com"));
+        assert!(other
+            .path()
+            .join(format!("file_{}.txt", history[0].time().seconds()))
+            .exists());
         Ok(())
     }
 
@@ -195,21 +1816,192 @@ mod history_rewriting {
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["old@example.com".to_string()],
+            patterns: vec!["old@example.com".into()],
             repo_path: repo.path().to_path_buf(),
             create_backup: false,
+            ..Config::default()
         };
 
         let rebrander = GitRebrander::new(config)?;
         rebrander.run()?;
 
         let git_repo = Repository::open(repo.path())?;
-        let branches: Vec<_> = git_repo
-            .branches(None)?
-            .map(|b| b.unwrap().0.name().unwrap().unwrap().to_string())
-            .collect();
+        let backups = git_repo.references_glob(&format!("{}*", BACKUP_NAMESPACE))?;
+        assert_eq!(backups.count(), 0);
+        Ok(())
+    }
+}
+
+mod undo {
+    use super::*;
+    use git_rebrand::journal;
+
+    #[test]
+    fn test_undo_restores_refs_and_records_history() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let original = repo.commit_with_author("Old work", "Old Author", "old@example.com")?;
+
+        GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+        let rewritten = repo.head_history()?[0].id();
+        assert_ne!(rewritten, original);
+
+        let runs = journal::history(repo.path())?;
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].refs.len(), 1);
+        assert_eq!(runs[0].refs[0].old, original.to_string());
+        assert_eq!(runs[0].refs[0].new, rewritten.to_string());
+
+        let undone = journal::undo(repo.path(), None)?;
+        assert_eq!(undone.id, runs[0].id);
+        assert_eq!(repo.head_history()?[0].id(), original);
+        assert!(journal::history(repo.path())?[0].undone_at.is_some());
+
+        // Nothing is left to undo
+        assert!(matches!(
+            journal::undo(repo.path(), None)
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::UndoFailed(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_refuses_moved_refs() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_with_author("Old work", "Old Author", "old@example.com")?;
+        GitRebrander::new(old_author_config(&repo, &[]))?.run()?;
+        let run_id = journal::history(repo.path())?[0].id.clone();
+
+        let head = repo.commit_with_author("New work", "Other", "other@example.com")?;
+
+        assert!(matches!(
+            journal::undo(repo.path(), Some(&run_id))
+                .unwrap_err()
+                .downcast::<GitRebrandError>()?,
+            GitRebrandError::UndoFailed(_)
+        ));
+        assert_eq!(repo.head_history()?[0].id(), head);
+        Ok(())
+    }
+}
+
+mod dirty_policy {
+    use super::*;
+    use git_rebrand::DirtyPolicy;
+
+    fn policy_config(repo: &TestRepo, dirty_policy: DirtyPolicy) -> Config {
+        Config {
+            content_replacements: vec![TextReplacement {
+                find: "OldCo".to_string(),
+                replace: "NewCo".to_string(),
+                regex: false,
+            }],
+            dirty_policy,
+            ..old_author_config(repo, &[])
+        }
+    }
+
+    #[test]
+    fn test_ignore_untracked_allows_build_artifacts() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo\n")])?;
+        repo.create_file("build.log", "artifact")?;
+
+        let strict = GitRebrander::new(policy_config(&repo, DirtyPolicy::Strict));
+        assert!(strict.is_err());
+
+        GitRebrander::new(policy_config(&repo, DirtyPolicy::IgnoreUntracked))?.run()?;
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("README.md"))?,
+            "NewCo\n"
+        );
+        assert!(repo.path().join("build.log").exists());
+
+        // Undo isn't blocked by the artifact either
+        git_rebrand::journal::undo(repo.path(), None)?;
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("README.md"))?,
+            "OldCo\n"
+        );
+        assert!(repo.path().join("build.log").exists());
+
+        // Changes to tracked files still block the rewrite
+        repo.create_file("README.md", "edited")?;
+        assert!(GitRebrander::new(policy_config(&repo, DirtyPolicy::IgnoreUntracked)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_stash_reapplies_changes() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files(
+            "Add files",
+            &[("README.md", b"OldCo\n"), ("notes.txt", b"notes\n")],
+        )?;
+        repo.create_file("notes.txt", "edited notes\n")?;
+        repo.create_file("scratch.txt", "untracked")?;
+
+        GitRebrander::new(policy_config(&repo, DirtyPolicy::AutoStash))?.run()?;
 
-        assert!(!branches.iter().any(|name| name.starts_with("backup_")));
+        let head = repo.head_history()?.remove(0);
+        assert_eq!(repo.file_at(&head, "README.md")?, b"NewCo\n");
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("notes.txt"))?,
+            "edited notes\n"
+        );
+        assert!(repo.path().join("scratch.txt").exists());
+        assert!(Repository::open(repo.path())?
+            .find_reference("refs/stash")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_stash_conflict_is_reported() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo\n")])?;
+        repo.create_file("README.md", "OldCo, edited\n")?;
+
+        let err = GitRebrander::new(policy_config(&repo, DirtyPolicy::AutoStash))?
+            .run()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast::<GitRebrandError>()?,
+            GitRebrandError::StashConflict(_)
+        ));
+
+        // The rewrite went through and the local edit is kept in the stash
+        let head = repo.head_history()?.remove(0);
+        assert_eq!(repo.file_at(&head, "README.md")?, b"NewCo\n");
+        assert!(Repository::open(repo.path())?
+            .find_reference("refs/stash")
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_stash_reapplied_when_run_fails() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.commit_files("Add readme", &[("README.md", b"OldCo\n")])?;
+        repo.create_file("scratch.txt", "work in progress")?;
+        // A file where the journal directory belongs makes recording the run fail
+        std::fs::write(repo.path().join(".git/rebrand"), "")?;
+
+        let result = GitRebrander::new(policy_config(&repo, DirtyPolicy::AutoStash))?.run();
+        assert!(result.is_err());
+        // The refs moved, so the working tree follows them and gets the changes back
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("README.md"))?,
+            "NewCo\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(repo.path().join("scratch.txt"))?,
+            "work in progress"
+        );
+        assert!(Repository::open(repo.path())?
+            .find_reference("refs/stash")
+            .is_err());
         Ok(())
     }
 }
@@ -225,9 +2017,9 @@ mod error_handling {
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["nonexistent@example.com".to_string()],
+            patterns: vec!["nonexistent@example.com".into()],
             repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            ..Config::default()
         };
 
         let rebrander = GitRebrander::new(config)?;
@@ -247,9 +2039,9 @@ mod error_handling {
         let config = Config {
             new_author_name: "New Author".to_string(),
             new_author_email: "new@example.com".to_string(),
-            patterns: vec!["test@example.com".to_string()],
+            patterns: vec!["test@example.com".into()],
             repo_path: repo.path().to_path_buf(),
-            create_backup: true,
+            ..Config::default()
         };
 
         let result = GitRebrander::new(config);